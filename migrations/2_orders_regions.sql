@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS regions (
+  id INT PRIMARY KEY,
+  name VARCHAR(50)
+);
+
+-- No foreign key from orders.region_id to regions.id: day 13 posts orders
+-- without ever posting their regions. POST /18/orders checks that the regions
+-- exist instead.
+CREATE TABLE IF NOT EXISTS orders (
+  id INT PRIMARY KEY,
+  region_id INT,
+  gift_name VARCHAR(50),
+  quantity INT
+);
@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS saved_strings (
+  key TEXT PRIMARY KEY,
+  saved_at TIMESTAMPTZ NOT NULL,
+  expires_at TIMESTAMPTZ
+);
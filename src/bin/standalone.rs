@@ -0,0 +1,73 @@
+//! Serves the same router as the Shuttle entrypoint with plain tokio + axum.
+//!
+//! ```sh
+//! cargo run --bin standalone -- --addr 0.0.0.0:8000 --database-url postgres://...
+//! ```
+//!
+//! `--addr` falls back to `BIND_ADDR` then `127.0.0.1:8000`, `--database-url` falls
+//! back to `DATABASE_URL`. Without a database the days needing one are not mounted
+//! and days 13 and 18 keep their orders in memory (see `ORDER_STORE`).
+//! Logs are filtered with `RUST_LOG` (e.g. `RUST_LOG=info`).
+
+use log::info;
+use ouchlock::days::{DaysConfig, Resources};
+use sqlx::PgPool;
+use std::{env, error::Error, net::SocketAddr};
+
+struct Args {
+    addr: SocketAddr,
+    database_url: Option<String>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut addr = env::var("BIND_ADDR").ok();
+    let mut database_url = env::var("DATABASE_URL").ok();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("missing value for {arg}"))?;
+        match arg.as_str() {
+            "--addr" => addr = Some(value),
+            "--database-url" => database_url = Some(value),
+            _ => return Err(format!("unknown argument {arg}")),
+        }
+    }
+
+    let addr = addr.unwrap_or_else(|| "127.0.0.1:8000".to_string());
+    let addr = addr
+        .parse()
+        .map_err(|err| format!("invalid address '{addr}': {err}"))?;
+
+    Ok(Args { addr, database_url })
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    tracing_subscriber::fmt::init();
+
+    let args = parse_args()?;
+    let config = DaysConfig::from_env()?;
+
+    let pool = match args.database_url {
+        Some(url) => {
+            let pool = PgPool::connect(&url).await?;
+            ouchlock::MIGRATOR.run(&pool).await?;
+            Some(pool)
+        }
+        None => None,
+    };
+    let resources = Resources::from_env(pool)?;
+    let router = ouchlock::app(&config, &resources);
+
+    info!("listening on {}", args.addr);
+    axum::Server::bind(&args.addr)
+        .serve(router.into_make_service())
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await?;
+
+    Ok(())
+}
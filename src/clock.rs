@@ -0,0 +1,243 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use crate::error::AppError;
+
+/// Where every time-aware handler gets the current time from.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+/// The operating system's clock.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+#[derive(Debug, Default)]
+struct FakeState {
+    /// Time returned while frozen.
+    frozen: Option<SystemTime>,
+    /// Added to the system time while running.
+    offset: Duration,
+}
+
+/// A clock following the system time until told otherwise: it can be frozen at
+/// any instant and moved forward.
+#[derive(Debug, Default)]
+pub struct FakeClock {
+    state: Mutex<FakeState>,
+}
+
+impl FakeClock {
+    fn state(&self) -> std::sync::MutexGuard<'_, FakeState> {
+        self.state.lock().expect("mutex was poisoned")
+    }
+
+    /// Stops the clock at `at`, or at the current time.
+    pub fn freeze(&self, at: Option<SystemTime>) {
+        let at = at.unwrap_or_else(|| self.now());
+        self.state().frozen = Some(at);
+    }
+
+    /// Moves the clock forward, whether it is frozen or not.
+    pub fn advance(&self, by: Duration) -> Result<(), AppError> {
+        let mut state = self.state();
+        let too_far = || AppError::BadRequest(format!("cannot advance the clock by {by:?}"));
+        match state.frozen {
+            Some(frozen) => state.frozen = Some(frozen.checked_add(by).ok_or_else(too_far)?),
+            None => {
+                let offset = state.offset.checked_add(by).ok_or_else(too_far)?;
+                SystemTime::now().checked_add(offset).ok_or_else(too_far)?;
+                state.offset = offset;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Goes back to the system time.
+    pub fn reset(&self) {
+        *self.state() = FakeState::default();
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.state().frozen.is_some()
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> SystemTime {
+        let state = self.state();
+        state
+            .frozen
+            .unwrap_or_else(|| SystemTime::now() + state.offset)
+    }
+}
+
+#[cfg(feature = "clock-admin")]
+pub use admin::get_routes;
+
+/// `/admin/clock` endpoints driving a [`FakeClock`], only built with the
+/// `clock-admin` feature.
+#[cfg(feature = "clock-admin")]
+mod admin {
+    use axum::{
+        extract::State,
+        routing::{get, post},
+        Router,
+    };
+    use chrono::{DateTime, SecondsFormat, Utc};
+    use log::info;
+    use std::{sync::Arc, time::Duration};
+
+    use super::{Clock, FakeClock};
+    use crate::error::AppError;
+    use crate::extract::{Json, Query};
+
+    pub fn get_routes(clock: Arc<FakeClock>) -> Router {
+        Router::new()
+            .route("/admin/clock", get(status))
+            .route("/admin/clock/freeze", post(freeze))
+            .route("/admin/clock/advance", post(advance))
+            .route("/admin/clock/reset", post(reset))
+            .with_state(clock)
+    }
+
+    #[derive(serde::Serialize, Debug)]
+    struct ClockStatus {
+        now: String,
+        frozen: bool,
+    }
+
+    fn status_of(clock: &FakeClock) -> Json<ClockStatus> {
+        Json(ClockStatus {
+            now: DateTime::<Utc>::from(clock.now()).to_rfc3339_opts(SecondsFormat::Millis, true),
+            frozen: clock.is_frozen(),
+        })
+    }
+
+    async fn status(State(clock): State<Arc<FakeClock>>) -> Json<ClockStatus> {
+        info!("admin clock status started");
+
+        status_of(&clock)
+    }
+
+    #[derive(serde::Deserialize, Debug, Default)]
+    struct FreezeParams {
+        /// RFC 3339 instant to freeze at, the current time by default.
+        at: Option<String>,
+    }
+
+    async fn freeze(
+        Query(params): Query<FreezeParams>,
+        State(clock): State<Arc<FakeClock>>,
+    ) -> Result<Json<ClockStatus>, AppError> {
+        info!("admin clock freeze started");
+        let at = match params.at {
+            Some(at) => Some(
+                DateTime::parse_from_rfc3339(&at)
+                    .map_err(|err| AppError::BadRequest(format!("invalid time '{at}': {err}")))?
+                    .into(),
+            ),
+            None => None,
+        };
+        clock.freeze(at);
+
+        Ok(status_of(&clock))
+    }
+
+    #[derive(serde::Deserialize, Debug)]
+    struct AdvanceParams {
+        seconds: u64,
+    }
+
+    async fn advance(
+        Query(params): Query<AdvanceParams>,
+        State(clock): State<Arc<FakeClock>>,
+    ) -> Result<Json<ClockStatus>, AppError> {
+        info!("admin clock advance started");
+        clock.advance(Duration::from_secs(params.seconds))?;
+
+        Ok(status_of(&clock))
+    }
+
+    async fn reset(State(clock): State<Arc<FakeClock>>) -> Json<ClockStatus> {
+        info!("admin clock reset started");
+        clock.reset();
+
+        status_of(&clock)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::days::time_store::{MemoryTimeStore, Saved, TimeStore};
+
+    fn at(seconds: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn frozen_clock_only_moves_when_advanced() {
+        let clock = FakeClock::default();
+        clock.freeze(Some(at(1_000)));
+        assert!(clock.is_frozen());
+        assert_eq!(clock.now(), at(1_000));
+        assert_eq!(clock.now(), at(1_000));
+
+        clock.advance(Duration::from_secs(60)).unwrap();
+        assert_eq!(clock.now(), at(1_060));
+    }
+
+    #[test]
+    fn running_clock_keeps_its_advance() {
+        let clock = FakeClock::default();
+        let day = Duration::from_secs(24 * 60 * 60);
+        clock.advance(day).unwrap();
+        assert!(!clock.is_frozen());
+        assert!(clock.now() >= SystemTime::now() + day - Duration::from_secs(1));
+
+        clock.freeze(None);
+        assert!(clock.now() >= SystemTime::now() + day - Duration::from_secs(1));
+
+        clock.reset();
+        assert!(!clock.is_frozen());
+        assert!(clock.now() < SystemTime::now() + day);
+    }
+
+    #[test]
+    fn advancing_past_the_end_of_time_fails() {
+        let clock = FakeClock::default();
+        clock.freeze(Some(at(0)));
+        assert!(clock.advance(Duration::MAX).is_err());
+        assert_eq!(clock.now(), at(0));
+    }
+
+    #[tokio::test]
+    async fn keys_expire_as_the_clock_advances() {
+        let clock = FakeClock::default();
+        clock.freeze(Some(at(1_000)));
+        let store = MemoryTimeStore::default();
+        let saved = Saved::new(clock.now(), Some(Duration::from_secs(5))).unwrap();
+        store.save("packet", saved).await.unwrap();
+
+        clock.advance(Duration::from_secs(4)).unwrap();
+        assert_eq!(
+            store.load("packet", clock.now()).await.unwrap(),
+            Some(at(1_000))
+        );
+        assert_eq!(store.sweep(clock.now()).await.unwrap(), 0);
+
+        clock.advance(Duration::from_secs(1)).unwrap();
+        assert_eq!(store.load("packet", clock.now()).await.unwrap(), None);
+        assert_eq!(store.sweep(clock.now()).await.unwrap(), 1);
+    }
+}
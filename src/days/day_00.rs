@@ -1,12 +1,22 @@
 use axum::{routing::get, Router};
 use log::info;
-use reqwest::StatusCode;
+
+use super::Day;
+use crate::error::AppError;
+
+pub const DAY: Day = Day {
+    number: -1,
+    title: "Warm-up",
+    requires: &[],
+    routes: &["GET /-1/error"],
+    router: |_| get_routes(),
+};
 
 pub fn get_routes() -> Router {
     Router::new().route("/-1/error", get(error))
 }
 
-async fn error() -> Result<String, StatusCode> {
+async fn error() -> Result<String, AppError> {
     info!("-1 started");
-    Err(StatusCode::INTERNAL_SERVER_ERROR)
+    Err(AppError::Internal("this endpoint always fails".to_string()))
 }
@@ -1,21 +1,190 @@
-use axum::{extract::Path, routing::get, Router};
+use axum::{
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
 use log::info;
-use reqwest::StatusCode;
+use num_bigint::BigInt;
+use num_traits::Zero;
+
+use super::Day;
+use crate::error::AppError;
+use crate::extract::{Json, Path, Query};
+
+pub const DAY: Day = Day {
+    number: 1,
+    title: "Exclusive cube",
+    requires: &[],
+    routes: &["GET /1/*key"],
+    router: |_| get_routes(),
+};
 
 pub fn get_routes() -> Router {
     Router::new().route("/1/*key", get(exclusive_cube))
 }
 
-async fn exclusive_cube(Path(params): Path<Vec<(String, String)>>) -> Result<String, StatusCode> {
+/// How the numbers of the path are combined, left to right.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum FoldOp {
+    #[default]
+    Xor,
+    Sum,
+    Product,
+    And,
+    Or,
+}
+
+impl FoldOp {
+    fn apply(&self, acc: &BigInt, x: &BigInt) -> BigInt {
+        match self {
+            FoldOp::Xor => acc ^ x,
+            FoldOp::Sum => acc + x,
+            FoldOp::Product => acc * x,
+            FoldOp::And => acc & x,
+            FoldOp::Or => acc | x,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            FoldOp::Xor => "xor",
+            FoldOp::Sum => "sum",
+            FoldOp::Product => "product",
+            FoldOp::And => "and",
+            FoldOp::Or => "or",
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Format {
+    /// Just the result, as the original puzzle expects.
+    #[default]
+    Text,
+    /// The result with every intermediate step.
+    Json,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct CubeParams {
+    #[serde(default)]
+    op: FoldOp,
+    /// Exponent applied to the folded value.
+    #[serde(default = "default_pow")]
+    pow: u32,
+    /// Modulus applied last, the result having its sign.
+    #[serde(rename = "mod")]
+    modulus: Option<String>,
+    #[serde(default)]
+    format: Format,
+}
+
+fn default_pow() -> u32 {
+    3
+}
+
+const MAX_POW: u32 = 256;
+/// Numbers in the path, the original puzzle sending at most 20.
+const MAX_NUMBERS: usize = 32;
+/// Digits of each number, sign excluded.
+const MAX_DIGITS: usize = 1_000;
+/// Bits of the power, about 30 000 digits, as raising to it and printing it
+/// take time growing faster than its size.
+const MAX_RESULT_BITS: u64 = 100_000;
+
+#[derive(serde::Serialize, Debug)]
+struct Step {
+    operation: String,
+    value: String,
+}
+
+#[derive(serde::Serialize, Debug)]
+struct Calculation {
+    op: FoldOp,
+    numbers: Vec<String>,
+    steps: Vec<Step>,
+    result: String,
+}
+
+fn parse_number(s: &str) -> Result<BigInt, AppError> {
+    if s.trim_start_matches(['+', '-']).len() > MAX_DIGITS {
+        return Err(AppError::BadRequest(format!(
+            "numbers must not have more than {MAX_DIGITS} digits"
+        )));
+    }
+
+    s.parse::<BigInt>()
+        .map_err(|err| AppError::BadRequest(format!("invalid number '{s}': {err}")))
+}
+
+/// Folds the numbers of the path with `op`, then raises the result to `pow` and
+/// reduces it modulo `mod`. Defaults to XOR then cube.
+async fn exclusive_cube(
+    Path(params): Path<Vec<(String, String)>>,
+    Query(query): Query<CubeParams>,
+) -> Result<Response, AppError> {
     info!("1 started");
-    let nums = params[0]
-        .1
+    let path = params
+        .first()
+        .map(|(_, path)| path.as_str())
+        .ok_or_else(|| AppError::BadRequest("no numbers given".to_string()))?;
+    if path.split('/').count() > MAX_NUMBERS {
+        return Err(AppError::BadRequest(format!(
+            "at most {MAX_NUMBERS} numbers can be given"
+        )));
+    }
+    let nums = path
         .split('/')
-        .map(|s| s.parse::<i32>().unwrap())
-        .collect::<Vec<i32>>();
+        .map(parse_number)
+        .collect::<Result<Vec<BigInt>, AppError>>()?;
+    if query.pow > MAX_POW {
+        return Err(AppError::BadRequest(format!(
+            "pow must not be greater than {MAX_POW}"
+        )));
+    }
+    let modulus = query.modulus.as_deref().map(parse_number).transpose()?;
+    if modulus.as_ref().is_some_and(Zero::is_zero) {
+        return Err(AppError::BadRequest("mod must not be 0".to_string()));
+    }
+
+    let mut steps = vec![];
+    let mut out = nums[0].clone();
+    for x in &nums[1..] {
+        out = query.op.apply(&out, x);
+        steps.push(Step {
+            operation: format!("{} {x}", query.op.name()),
+            value: out.to_string(),
+        });
+    }
+    if out.bits().saturating_mul(u64::from(query.pow)) > MAX_RESULT_BITS {
+        return Err(AppError::BadRequest(format!(
+            "the result would have more than {MAX_RESULT_BITS} bits"
+        )));
+    }
+    out = out.pow(query.pow);
+    steps.push(Step {
+        operation: format!("pow {}", query.pow),
+        value: out.to_string(),
+    });
+    if let Some(m) = modulus {
+        out = ((out % &m) + &m) % &m;
+        steps.push(Step {
+            operation: format!("mod {m}"),
+            value: out.to_string(),
+        });
+    }
 
-    let out = nums[1..nums.len()].iter().fold(nums[0], |acc, x| acc ^ x);
-    let out = out.pow(3);
+    if query.format == Format::Text {
+        return Ok(out.to_string().into_response());
+    }
 
-    Ok(out.to_string())
+    Ok(Json(Calculation {
+        op: query.op,
+        numbers: nums.iter().map(BigInt::to_string).collect(),
+        steps,
+        result: out.to_string(),
+    })
+    .into_response())
 }
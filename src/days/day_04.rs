@@ -1,11 +1,31 @@
-use axum::{routing::post, Json, Router};
+use axum::{routing::post, Router};
 use log::info;
-use reqwest::StatusCode;
+use std::collections::BTreeMap;
+
+use super::Day;
+use crate::error::AppError;
+use crate::extract::{Json, Query};
+use crate::stats::{parse_percentiles, percentile_cont};
+
+pub const DAY: Day = Day {
+    number: 4,
+    title: "Reindeer strength contest",
+    requires: &[],
+    routes: &[
+        "POST /4/strength",
+        "POST /4/contest",
+        "POST /4/leaderboard",
+        "POST /4/stats",
+    ],
+    router: |_| get_routes(),
+};
 
 pub fn get_routes() -> Router {
     Router::new()
         .route("/4/strength", post(strength))
         .route("/4/contest", post(contest))
+        .route("/4/leaderboard", post(leaderboard))
+        .route("/4/stats", post(stats))
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -13,13 +33,12 @@ struct Reindeer {
     strength: u32,
 }
 
-async fn strength(Json(reindeers): Json<Vec<Reindeer>>) -> Result<String, StatusCode> {
+async fn strength(Json(reindeers): Json<Vec<Reindeer>>) -> Result<String, AppError> {
     info!("4 strength started");
-    let mut sum = 0;
-
-    for reindeer in reindeers {
-        sum += reindeer.strength;
-    }
+    let sum = reindeers
+        .iter()
+        .map(|reindeer| u64::from(reindeer.strength))
+        .sum::<u64>();
 
     Ok(sum.to_string())
 }
@@ -28,7 +47,7 @@ async fn strength(Json(reindeers): Json<Vec<Reindeer>>) -> Result<String, Status
 struct Champion {
     name: String,
     strength: u32,
-    speed: f32,
+    speed: f64,
     height: u32,
     antler_width: u32,
     snow_magic_power: u32,
@@ -37,42 +56,373 @@ struct Champion {
     candies: u32,
 }
 
-async fn contest(Json(champions): Json<Vec<Champion>>) -> Result<String, StatusCode> {
-    info!("4 contest started");
-    let mut fastest = champions[0].clone();
-    let mut tallest = champions[0].clone();
-    let mut magician = champions[0].clone();
-    let mut consumer = champions[0].clone();
-
-    for champ in champions {
-        if champ.speed > fastest.speed {
-            fastest = champ.clone();
+/// A numeric attribute of a [`Champion`], named as in the request body.
+#[derive(
+    serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[serde(rename_all = "snake_case")]
+enum Field {
+    Strength,
+    Speed,
+    Height,
+    AntlerWidth,
+    SnowMagicPower,
+    #[serde(rename = "cAnD13s_3ATeN-yesT3rdAy", alias = "candies")]
+    Candies,
+}
+
+impl Field {
+    const ALL: [Field; 6] = [
+        Field::Strength,
+        Field::Speed,
+        Field::Height,
+        Field::AntlerWidth,
+        Field::SnowMagicPower,
+        Field::Candies,
+    ];
+
+    fn value(&self, champion: &Champion) -> f64 {
+        match self {
+            Field::Strength => champion.strength.into(),
+            Field::Speed => champion.speed,
+            Field::Height => champion.height.into(),
+            Field::AntlerWidth => champion.antler_width.into(),
+            Field::SnowMagicPower => champion.snow_magic_power.into(),
+            Field::Candies => champion.candies.into(),
         }
-        if champ.height > tallest.height {
-            tallest = champ.clone();
+    }
+
+    /// The value as written in the body: integers stay integers.
+    fn json(&self, champion: &Champion) -> serde_json::Value {
+        match self {
+            Field::Speed => serde_json::json!(self.value(champion)),
+            _ => serde_json::json!(self.value(champion) as u64),
         }
-        if champ.snow_magic_power > magician.snow_magic_power {
-            magician = champ.clone();
+    }
+}
+
+/// Fills `{field}` placeholders of `template` with the champion's attributes.
+fn render(template: &str, champion: &Champion) -> Result<String, String> {
+    let mut message = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        let close = rest[open..]
+            .find('}')
+            .ok_or_else(|| format!("unclosed placeholder in '{template}'"))?;
+        let placeholder = &rest[open + 1..open + close];
+        message.push_str(&rest[..open]);
+        match placeholder {
+            "name" => message.push_str(&champion.name),
+            "favorite_food" => message.push_str(&champion.favorite_food),
+            _ => {
+                let field = serde_json::from_value::<Field>(placeholder.into())
+                    .map_err(|_| format!("unknown placeholder {{{placeholder}}}"))?;
+                message.push_str(&field.json(champion).to_string());
+            }
         }
-        if champ.candies > consumer.candies {
-            consumer = champ.clone();
+        rest = &rest[open + close + 1..];
+    }
+    message.push_str(rest);
+
+    Ok(message)
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Direction {
+    #[default]
+    Max,
+    Min,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+struct Category {
+    name: String,
+    field: Field,
+    #[serde(default)]
+    direction: Direction,
+    /// Message about a leader, see [`render`].
+    message: String,
+}
+
+/// The categories of the original contest.
+fn default_categories() -> Vec<Category> {
+    let category = |name: &str, field, message: &str| Category {
+        name: name.to_string(),
+        field,
+        direction: Direction::Max,
+        message: message.to_string(),
+    };
+
+    vec![
+        category(
+            "fastest",
+            Field::Speed,
+            "Speeding past the finish line with a strength of {strength} is {name}",
+        ),
+        category(
+            "tallest",
+            Field::Height,
+            "{name} is standing tall with his {antler_width} cm wide antlers",
+        ),
+        category(
+            "magician",
+            Field::SnowMagicPower,
+            "{name} could blast you away with a snow magic power of {snow_magic_power}",
+        ),
+        category(
+            "consumer",
+            Field::Candies,
+            "{name} ate lots of candies, but also some {favorite_food}",
+        ),
+    ]
+}
+
+#[derive(serde::Serialize, Debug)]
+struct Leader {
+    rank: usize,
+    /// Whether another champion has the same rank.
+    tied: bool,
+    name: String,
+    value: serde_json::Value,
+    message: String,
+}
+
+#[derive(serde::Serialize, Debug)]
+struct Board {
+    category: String,
+    field: Field,
+    direction: Direction,
+    leaders: Vec<Leader>,
+}
+
+/// Champions of the `top` first ranks of `category`, tied ones sharing a rank
+/// (1, 2, 2, 4) and listed in input order.
+fn board(champions: &[Champion], category: &Category, top: usize) -> Result<Board, AppError> {
+    let value = |champion: &Champion| category.field.value(champion);
+    let mut sorted = champions.iter().collect::<Vec<_>>();
+    sorted.sort_by(|a, b| match category.direction {
+        Direction::Max => value(b).total_cmp(&value(a)),
+        Direction::Min => value(a).total_cmp(&value(b)),
+    });
+
+    let ranks = sorted
+        .iter()
+        .enumerate()
+        .scan(None, |prev: &mut Option<(f64, usize)>, (i, champion)| {
+            let rank = match *prev {
+                Some((prev_value, rank)) if prev_value == value(champion) => rank,
+                _ => i + 1,
+            };
+            *prev = Some((value(champion), rank));
+            Some(rank)
+        })
+        .collect::<Vec<_>>();
+
+    let mut leaders = vec![];
+    for (i, (champion, rank)) in sorted.iter().zip(&ranks).enumerate() {
+        if *rank > top {
+            break;
         }
+        let tied = (i > 0 && ranks[i - 1] == *rank) || ranks.get(i + 1) == Some(rank);
+        let message = render(&category.message, champion).map_err(|err| {
+            AppError::BadRequest(format!("invalid category '{}': {err}", category.name))
+        })?;
+        leaders.push(Leader {
+            rank: *rank,
+            tied,
+            name: champion.name.clone(),
+            value: category.field.json(champion),
+            message,
+        });
     }
 
-    Ok(format!(
-        "{{
-    \"fastest\": \"Speeding past the finish line with a strength of {} is {}\",
-    \"tallest\": \"{} is standing tall with his {} cm wide antlers\",
-    \"magician\": \"{} could blast you away with a snow magic power of {}\",
-    \"consumer\": \"{} ate lots of candies, but also some {}\"
-}}",
-        fastest.strength,
-        fastest.name,
-        tallest.name,
-        tallest.antler_width,
-        magician.name,
-        magician.snow_magic_power,
-        consumer.name,
-        consumer.favorite_food
-    ))
+    Ok(Board {
+        category: category.name.clone(),
+        field: category.field,
+        direction: category.direction,
+        leaders,
+    })
+}
+
+fn check_not_empty(champions: &[Champion]) -> Result<(), AppError> {
+    if champions.is_empty() {
+        return Err(AppError::BadRequest(
+            "no reindeer in the contest".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+async fn contest(
+    Json(champions): Json<Vec<Champion>>,
+) -> Result<Json<BTreeMap<String, String>>, AppError> {
+    info!("4 contest started");
+    check_not_empty(&champions)?;
+
+    let mut messages = BTreeMap::new();
+    for category in default_categories() {
+        let board = board(&champions, &category, 1)?;
+        let leader = board
+            .leaders
+            .into_iter()
+            .next()
+            .expect("champions is not empty");
+        messages.insert(board.category, leader.message);
+    }
+
+    Ok(messages.into())
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct LeaderboardRequest {
+    champions: Vec<Champion>,
+    /// The original contest's categories by default.
+    #[serde(default = "default_categories")]
+    categories: Vec<Category>,
+    /// How many ranks to keep per category.
+    #[serde(default = "default_top")]
+    top: usize,
+}
+
+fn default_top() -> usize {
+    3
+}
+
+async fn leaderboard(
+    Json(request): Json<LeaderboardRequest>,
+) -> Result<Json<Vec<Board>>, AppError> {
+    info!("4 leaderboard started");
+    check_not_empty(&request.champions)?;
+
+    let boards = request
+        .categories
+        .iter()
+        .map(|category| board(&request.champions, category, request.top))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(boards.into())
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum GroupBy {
+    FavoriteFood,
+}
+
+#[derive(serde::Deserialize, Debug, Default)]
+struct StatsParams {
+    group_by: Option<GroupBy>,
+    /// Comma separated percentiles between 0 and 1, e.g. `0.5,0.9`.
+    p: Option<String>,
+}
+
+#[derive(serde::Serialize, Debug)]
+struct PercentileValue {
+    percentile: f64,
+    value: f64,
+}
+
+#[derive(serde::Serialize, Debug)]
+struct FieldStats {
+    count: usize,
+    min: f64,
+    max: f64,
+    mean: f64,
+    median: f64,
+    /// Population standard deviation.
+    stddev: f64,
+    percentiles: Vec<PercentileValue>,
+}
+
+#[derive(serde::Serialize, Debug)]
+struct GroupStats {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    favorite_food: Option<String>,
+    count: usize,
+    fields: BTreeMap<Field, FieldStats>,
+}
+
+/// Summarizes non-empty `values`. Works on `f64` so nothing overflows, with
+/// Welford's algorithm keeping the variance accurate on large sets.
+fn field_stats(mut values: Vec<f64>, percentiles: &[f64]) -> FieldStats {
+    values.sort_by(f64::total_cmp);
+
+    let (mut mean, mut m2) = (0.0, 0.0);
+    for (i, value) in values.iter().enumerate() {
+        let delta = value - mean;
+        mean += delta / (i + 1) as f64;
+        m2 += delta * (value - mean);
+    }
+
+    FieldStats {
+        count: values.len(),
+        min: values[0],
+        max: values[values.len() - 1],
+        mean,
+        median: percentile_cont(&values, 0.5),
+        stddev: (m2 / values.len() as f64).sqrt(),
+        percentiles: percentiles
+            .iter()
+            .map(|&percentile| PercentileValue {
+                percentile,
+                value: percentile_cont(&values, percentile),
+            })
+            .collect(),
+    }
+}
+
+fn group_stats(
+    favorite_food: Option<String>,
+    champions: &[&Champion],
+    percentiles: &[f64],
+) -> GroupStats {
+    let fields = Field::ALL
+        .iter()
+        .map(|field| {
+            let values = champions.iter().map(|c| field.value(c)).collect();
+            (*field, field_stats(values, percentiles))
+        })
+        .collect();
+
+    GroupStats {
+        favorite_food,
+        count: champions.len(),
+        fields,
+    }
+}
+
+async fn stats(
+    Query(params): Query<StatsParams>,
+    Json(champions): Json<Vec<Champion>>,
+) -> Result<Json<Vec<GroupStats>>, AppError> {
+    info!("4 stats started");
+    check_not_empty(&champions)?;
+    let percentiles = parse_percentiles(params.p.as_deref())?;
+
+    let groups = match params.group_by {
+        None => vec![group_stats(
+            None,
+            &champions.iter().collect::<Vec<_>>(),
+            &percentiles,
+        )],
+        Some(GroupBy::FavoriteFood) => {
+            let mut groups: BTreeMap<&str, Vec<&Champion>> = BTreeMap::new();
+            for champion in &champions {
+                groups
+                    .entry(champion.favorite_food.as_str())
+                    .or_default()
+                    .push(champion);
+            }
+            groups
+                .into_iter()
+                .map(|(food, champions)| {
+                    group_stats(Some(food.to_string()), &champions, &percentiles)
+                })
+                .collect()
+        }
+    };
+
+    Ok(groups.into())
 }
@@ -1,9 +1,29 @@
-use axum::{extract::Query, routing::post, Json, Router};
+use axum::{
+    extract::RawBody,
+    http::{header::LINK, HeaderMap, HeaderValue, Uri},
+    routing::post,
+    Router,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use log::info;
-use reqwest::StatusCode;
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use super::Day;
+use crate::error::AppError;
+use crate::extract::{fold_json_array, Json, Query, BODY_LIMIT};
+
+pub const DAY: Day = Day {
+    number: 5,
+    title: "Slicing the loop",
+    requires: &[],
+    routes: &["POST /5", "POST /5/page"],
+    router: |_| get_routes(),
+};
 
 pub fn get_routes() -> Router {
-    Router::new().route("/5", post(slicing_the_loop))
+    Router::new()
+        .route("/5", post(slicing_the_loop))
+        .route("/5/page", post(page))
 }
 
 #[derive(serde::Deserialize, Debug, Default)]
@@ -16,40 +36,228 @@ struct Pagination {
     split: usize,
 }
 
+#[derive(serde::Serialize, Debug)]
+#[serde(untagged)]
+enum Slice {
+    Names(Vec<String>),
+    Chunks(Vec<Vec<String>>),
+}
+
 async fn slicing_the_loop(
-    pagination: Query<Pagination>,
-    Json(names): Json<Vec<String>>,
-) -> Result<String, (StatusCode, String)> {
+    Query(pagination): Query<Pagination>,
+    RawBody(body): RawBody,
+) -> Result<Json<Slice>, AppError> {
     info!("5 started");
-    let start = if let Some(start) = pagination.offset {
-        if start > names.len() {
-            names.len()
-        } else {
-            start
-        }
-    } else {
-        0
+    let start = pagination.offset.unwrap_or(0);
+    let end = match pagination.limit {
+        Some(limit) => start.saturating_add(limit),
+        None => usize::MAX,
     };
 
-    let end = if let Some(limit) = pagination.limit {
-        if start + limit > names.len() {
-            names.len()
-        } else {
-            start + limit
+    // Every name may be kept, so the body is bounded as if it were buffered.
+    let (names, _) = fold_json_array(
+        body,
+        Some(BODY_LIMIT),
+        (vec![], 0),
+        move |(names, i), name: String| {
+            if (start..end).contains(i) {
+                names.push(name);
+            }
+            *i += 1;
+        },
+    )
+    .await?;
+
+    if pagination.split == 0 {
+        return Ok(Json(Slice::Names(names)));
+    }
+
+    let chunks = names
+        .chunks(pagination.split)
+        .map(|chunk| chunk.to_vec())
+        .collect();
+
+    Ok(Json(Slice::Chunks(chunks)))
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum SortOrder {
+    Asc,
+    Desc,
+}
+
+/// Where a page starts and which names it is taken from. Sent to clients as an
+/// opaque token so they can only follow the `next`/`prev` links.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
+struct Cursor {
+    offset: usize,
+    sort: Option<SortOrder>,
+    q: Option<String>,
+}
+
+impl Cursor {
+    fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).expect("cursors are serializable"))
+    }
+
+    fn decode(token: &str) -> Result<Self, AppError> {
+        URL_SAFE_NO_PAD
+            .decode(token)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .ok_or_else(|| AppError::BadRequest(format!("invalid cursor '{token}'")))
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        self.q
+            .as_ref()
+            .is_none_or(|q| name.to_lowercase().contains(&q.to_lowercase()))
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Default)]
+struct PageParams {
+    /// Token from a previous page. Its sort and filter override `sort` and `q`.
+    cursor: Option<String>,
+    #[serde(default = "default_limit")]
+    limit: usize,
+    /// Input order by default.
+    sort: Option<SortOrder>,
+    /// Case-insensitive substring the names must contain.
+    q: Option<String>,
+}
+
+fn default_limit() -> usize {
+    20
+}
+
+const MAX_LIMIT: usize = 1000;
+
+#[derive(serde::Serialize, Debug)]
+struct Page {
+    names: Vec<String>,
+    /// Names matching the filter.
+    total: usize,
+    next: Option<String>,
+    prev: Option<String>,
+}
+
+/// Names of the page, whatever their order, and how many names match the filter.
+/// Sorted pages keep only the first `end` names in order in a bounded heap.
+#[derive(Default)]
+struct Window {
+    names: Vec<String>,
+    asc: BinaryHeap<String>,
+    desc: BinaryHeap<Reverse<String>>,
+    total: usize,
+}
+
+impl Window {
+    fn push(&mut self, cursor: &Cursor, end: usize, name: String) {
+        if !cursor.matches(&name) {
+            return;
         }
-    } else {
-        names.len()
+        match cursor.sort {
+            None if (cursor.offset..end).contains(&self.total) => self.names.push(name),
+            None => {}
+            Some(SortOrder::Asc) => {
+                self.asc.push(name);
+                if self.asc.len() > end {
+                    self.asc.pop();
+                }
+            }
+            Some(SortOrder::Desc) => {
+                self.desc.push(Reverse(name));
+                if self.desc.len() > end {
+                    self.desc.pop();
+                }
+            }
+        }
+        self.total += 1;
+    }
+
+    fn into_page(self, offset: usize) -> Vec<String> {
+        let sorted = match (self.asc.is_empty(), self.desc.is_empty()) {
+            (false, _) => self.asc.into_sorted_vec(),
+            (_, false) => self
+                .desc
+                .into_sorted_vec()
+                .into_iter()
+                .map(|Reverse(name)| name)
+                .collect(),
+            _ => return self.names,
+        };
+
+        sorted.into_iter().skip(offset).collect()
+    }
+}
+
+fn link(uri: &Uri, token: &str, limit: usize, rel: &str) -> String {
+    format!(
+        "<{}?cursor={token}&limit={limit}>; rel=\"{rel}\"",
+        uri.path()
+    )
+}
+
+/// One page of the posted names, parsed as they are received. Follow the `next`
+/// and `prev` tokens (also in the `Link` header) with the same body.
+async fn page(
+    uri: Uri,
+    Query(params): Query<PageParams>,
+    RawBody(body): RawBody,
+) -> Result<(HeaderMap, Json<Page>), AppError> {
+    info!("5 page started");
+    if !(1..=MAX_LIMIT).contains(&params.limit) {
+        return Err(AppError::BadRequest(format!(
+            "limit must be between 1 and {MAX_LIMIT}"
+        )));
+    }
+    let cursor = match &params.cursor {
+        Some(token) => Cursor::decode(token)?,
+        None => Cursor {
+            offset: 0,
+            sort: params.sort,
+            q: params.q,
+        },
     };
+    let limit = params.limit;
+    let end = cursor.offset.saturating_add(limit);
 
-    if pagination.split == 0 {
-        let out = serde_json::to_string(&names[start..end].to_vec()).unwrap();
-        Ok(out)
-    } else {
-        let chunks = names[start..end]
-            .chunks(pagination.split)
-            .map(|chunk| chunk.to_vec())
-            .collect::<Vec<_>>();
-        let out = serde_json::to_string(&chunks).unwrap();
-        Ok(out)
+    let (window, cursor) = fold_json_array(
+        body,
+        None,
+        (Window::default(), cursor),
+        move |(window, cursor), name: String| window.push(cursor, end, name),
+    )
+    .await?;
+
+    let total = window.total;
+    let next = (end < total).then(|| Cursor {
+        offset: end,
+        ..cursor.clone()
+    });
+    let prev = (cursor.offset > 0).then(|| Cursor {
+        offset: cursor.offset.saturating_sub(limit),
+        ..cursor.clone()
+    });
+    let page = Page {
+        names: window.into_page(cursor.offset),
+        total,
+        next: next.as_ref().map(Cursor::encode),
+        prev: prev.as_ref().map(Cursor::encode),
+    };
+
+    let links = [(&page.next, "next"), (&page.prev, "prev")]
+        .into_iter()
+        .filter_map(|(token, rel)| Some(link(&uri, token.as_ref()?, limit, rel)))
+        .collect::<Vec<_>>();
+    let mut headers = HeaderMap::new();
+    if !links.is_empty() {
+        let value = HeaderValue::from_str(&links.join(", "))
+            .map_err(|err| AppError::Internal(err.to_string()))?;
+        headers.insert(LINK, value);
     }
+
+    Ok((headers, Json(page)))
 }
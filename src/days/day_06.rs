@@ -1,14 +1,177 @@
-use axum::{routing::post, Json, Router};
+use axum::{extract::State, routing::post, Router};
 use log::info;
-use regex::Regex;
-use reqwest::StatusCode;
+use regex::{Regex, RegexBuilder};
+use std::sync::{Arc, Mutex};
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::Day;
+use crate::error::AppError;
+use crate::extract::{Json, Query};
+use crate::lru::LruMap;
+
+pub const DAY: Day = Day {
+    number: 6,
+    title: "Elf on a shelf",
+    requires: &[],
+    routes: &["POST /6", "POST /6/count"],
+    router: |_| get_routes(),
+};
 
 pub fn get_routes() -> Router {
-    Router::new().route("/6", post(elf_count))
+    Router::new()
+        .route("/6", post(elf_count))
+        .route("/6/count", post(count))
+        .with_state(Arc::new(PatternCache::default()))
+}
+
+const MAX_PATTERNS: usize = 32;
+const MAX_PATTERN_LEN: usize = 256;
+/// Bytes a compiled regex may take, rejecting patterns like `(a{1000}){1000}`.
+const MAX_REGEX_SIZE: usize = 1 << 20;
+const CACHE_SIZE: usize = 256;
+/// Offsets returned per pattern, counts are always complete.
+const MAX_OFFSETS: usize = 1000;
+
+#[derive(serde::Deserialize, Debug, Clone)]
+struct Pattern {
+    pattern: String,
+    /// Whether `pattern` is a regex rather than a literal string.
+    #[serde(default)]
+    regex: bool,
+    #[serde(default)]
+    case_insensitive: bool,
+    /// Whether a match may start inside the previous one.
+    #[serde(default)]
+    overlapping: bool,
+}
+
+impl Pattern {
+    fn literal(pattern: &str, overlapping: bool) -> Self {
+        Pattern {
+            pattern: pattern.to_string(),
+            regex: false,
+            case_insensitive: false,
+            overlapping,
+        }
+    }
+
+    fn compile(&self) -> Result<Regex, String> {
+        if self.pattern.is_empty() || self.pattern.len() > MAX_PATTERN_LEN {
+            return Err(format!(
+                "must be between 1 and {MAX_PATTERN_LEN} bytes long"
+            ));
+        }
+        let pattern = if self.regex {
+            self.pattern.clone()
+        } else {
+            regex::escape(&self.pattern)
+        };
+
+        RegexBuilder::new(&pattern)
+            .case_insensitive(self.case_insensitive)
+            .size_limit(MAX_REGEX_SIZE)
+            .dfa_size_limit(MAX_REGEX_SIZE)
+            .build()
+            .map_err(|err| err.to_string())
+    }
+
+    /// Byte offsets of the matches in `text`.
+    fn offsets(&self, regex: &Regex, text: &str) -> Vec<usize> {
+        if !self.overlapping {
+            return regex.find_iter(text).map(|m| m.start()).collect();
+        }
+
+        let mut offsets = vec![];
+        let mut start = 0;
+        while let Some(m) = regex.find_at(text, start) {
+            offsets.push(m.start());
+            // Restart on the next character, even after an empty match.
+            match text[m.start()..].chars().next() {
+                Some(c) => start = m.start() + c.len_utf8(),
+                None => break,
+            }
+        }
+
+        offsets
+    }
+}
+
+/// What a compiled regex is cached under: the pattern and how it was compiled.
+type PatternKey = (String, bool, bool);
+
+/// Compiled regexes shared by every request, at most [`CACHE_SIZE`] of them,
+/// dropping the least recently used one. Patterns are compiled without holding
+/// the lock, so a slow one only delays its own request.
+struct PatternCache {
+    regexes: Mutex<LruMap<PatternKey, Regex>>,
+}
+
+impl Default for PatternCache {
+    fn default() -> Self {
+        PatternCache {
+            regexes: Mutex::new(LruMap::new(CACHE_SIZE)),
+        }
+    }
+}
+
+impl PatternCache {
+    fn regexes(&self) -> std::sync::MutexGuard<'_, LruMap<PatternKey, Regex>> {
+        self.regexes.lock().expect("mutex was poisoned")
+    }
+
+    fn get(&self, pattern: &Pattern) -> Result<Regex, String> {
+        let key = (
+            pattern.pattern.clone(),
+            pattern.regex,
+            pattern.case_insensitive,
+        );
+        if let Some(regex) = self.regexes().get(&key) {
+            return Ok(regex.clone());
+        }
+
+        let regex = pattern.compile()?;
+        self.regexes().insert(key, regex.clone());
+
+        Ok(regex)
+    }
+
+    fn count(&self, pattern: &Pattern, text: &str) -> Result<usize, AppError> {
+        let regex = self
+            .get(pattern)
+            .map_err(|err| AppError::Internal(format!("invalid built-in pattern: {err}")))?;
+
+        Ok(pattern.offsets(&regex, text).len())
+    }
+}
+
+/// How elves and shelves are recognized in the text.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Mode {
+    /// Raw substrings, as the original puzzle expects: "shelf" and "self" hold an elf.
+    #[default]
+    Substring,
+    /// Whole Unicode words, ignoring case and plurals: "Elves" are elves, "shelf" is not.
+    Words,
+}
+
+impl Mode {
+    fn is_substring(&self) -> bool {
+        *self == Mode::Substring
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Default)]
+struct ElfParams {
+    #[serde(default)]
+    mode: Mode,
 }
 
 #[derive(serde::Serialize, Debug)]
 struct ElfCount {
+    /// Left out in the default mode, whose output the puzzle checks as is.
+    #[serde(skip_serializing_if = "Mode::is_substring")]
+    mode: Mode,
     elf: usize,
     #[serde(rename = "elf on a shelf")]
     elf_on_a_shelf: usize,
@@ -16,21 +179,151 @@ struct ElfCount {
     no_elf: usize,
 }
 
-async fn elf_count(body: String) -> Result<Json<ElfCount>, StatusCode> {
-    info!("6 started");
-    let elf = body.matches("elf").count();
+const ELF_ON_A_SHELF: [&str; 4] = ["elf", "on", "a", "shelf"];
+
+/// Lowercases `word`, drops a possessive and makes plural elves and shelves singular.
+fn normalize(word: &str) -> String {
+    let word = word.to_lowercase();
+    let word = word
+        .strip_suffix("'s")
+        .or_else(|| word.strip_suffix("\u{2019}s"))
+        .unwrap_or(&word);
+
+    match word {
+        "elves" | "elfs" => "elf".to_string(),
+        "shelves" => "shelf".to_string(),
+        _ => word.to_string(),
+    }
+}
+
+fn count_words(text: &str) -> ElfCount {
+    let words = text.unicode_words().map(normalize).collect::<Vec<_>>();
+    let count = |target: &str| words.iter().filter(|word| *word == target).count();
+    let elf_on_a_shelf = words
+        .windows(ELF_ON_A_SHELF.len())
+        .filter(|window| window.iter().eq(ELF_ON_A_SHELF.iter()))
+        .count();
+
+    ElfCount {
+        mode: Mode::Words,
+        elf: count("elf"),
+        elf_on_a_shelf,
+        no_elf: count("shelf") - elf_on_a_shelf,
+    }
+}
 
-    let mut elf_on_a_shelf = 0;
-    let re = Regex::new(r"(elf( on a shelf)+)").unwrap();
-    for cap in re.captures_iter(body.as_str()) {
-        elf_on_a_shelf += cap[1].matches(" on a shelf").count();
+async fn elf_count(
+    Query(params): Query<ElfParams>,
+    State(cache): State<Arc<PatternCache>>,
+    body: String,
+) -> Result<Json<ElfCount>, AppError> {
+    info!("6 started");
+    if params.mode == Mode::Words {
+        return Ok(Json(count_words(&body)));
     }
 
-    let no_elf = body.matches("shelf").count() - elf_on_a_shelf;
+    let elf = cache.count(&Pattern::literal("elf", false), &body)?;
+    // Overlapping, so "elf on a shelf on a shelf" holds two of them.
+    let elf_on_a_shelf = cache.count(&Pattern::literal("elf on a shelf", true), &body)?;
+    let shelves = cache.count(&Pattern::literal("shelf", false), &body)?;
 
     Ok(Json(ElfCount {
+        mode: Mode::Substring,
         elf,
         elf_on_a_shelf,
-        no_elf,
+        no_elf: shelves - elf_on_a_shelf,
     }))
 }
+
+#[derive(serde::Deserialize, Debug)]
+struct NamedPattern {
+    name: String,
+    #[serde(flatten)]
+    pattern: Pattern,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct CountRequest {
+    text: String,
+    patterns: Vec<NamedPattern>,
+}
+
+#[derive(serde::Serialize, Debug)]
+struct PatternCount {
+    name: String,
+    count: usize,
+    /// Byte offsets of the first matches.
+    offsets: Vec<usize>,
+}
+
+async fn count(
+    State(cache): State<Arc<PatternCache>>,
+    Json(request): Json<CountRequest>,
+) -> Result<Json<Vec<PatternCount>>, AppError> {
+    info!("6 count started");
+    if request.patterns.is_empty() || request.patterns.len() > MAX_PATTERNS {
+        return Err(AppError::BadRequest(format!(
+            "between 1 and {MAX_PATTERNS} patterns are needed"
+        )));
+    }
+
+    let mut counts = vec![];
+    for named in request.patterns {
+        let regex = cache.get(&named.pattern).map_err(|err| {
+            AppError::BadRequest(format!("invalid pattern '{}': {err}", named.name))
+        })?;
+        let mut offsets = named.pattern.offsets(&regex, &request.text);
+        let count = offsets.len();
+        offsets.truncate(MAX_OFFSETS);
+
+        counts.push(PatternCount {
+            name: named.name,
+            count,
+            offsets,
+        });
+    }
+
+    Ok(counts.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cached(cache: &PatternCache, pattern: &str) -> bool {
+        cache
+            .regexes()
+            .get(&(pattern.to_string(), false, false))
+            .is_some()
+    }
+
+    #[test]
+    fn cache_evicts_the_least_recently_used_pattern() {
+        let cache = PatternCache::default();
+        for i in 0..CACHE_SIZE {
+            cache.get(&Pattern::literal(&i.to_string(), false)).unwrap();
+        }
+        // Reading the first pattern again makes the second one the oldest.
+        cache.get(&Pattern::literal("0", false)).unwrap();
+        cache.get(&Pattern::literal("new", false)).unwrap();
+
+        assert_eq!(cache.regexes().len(), CACHE_SIZE);
+        assert!(cached(&cache, "0"));
+        assert!(!cached(&cache, "1"));
+        assert!(cached(&cache, "2"));
+        assert!(cached(&cache, "new"));
+    }
+
+    #[test]
+    fn invalid_patterns_are_not_cached() {
+        let cache = PatternCache::default();
+        let invalid = Pattern {
+            pattern: "(".to_string(),
+            regex: true,
+            case_insensitive: false,
+            overlapping: false,
+        };
+        assert!(cache.get(&invalid).is_err());
+        assert!(cache.regexes().is_empty());
+    }
+}
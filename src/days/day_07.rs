@@ -1,29 +1,176 @@
-use axum::{http::HeaderMap, routing::get, Json, Router};
+use axum::{
+    extract::State,
+    http::{
+        header::{HeaderName, SET_COOKIE},
+        StatusCode,
+    },
+    routing::{get, post},
+    Router,
+};
 use base64::{engine::general_purpose, Engine};
-use cookie::Cookie;
-use log::info;
-use reqwest::StatusCode;
-use serde_json::{json, Value};
-use std::collections::HashMap;
+use cookie::{Cookie, CookieJar, Key};
+use log::{info, warn};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    sync::Arc,
+};
 
-pub fn get_routes() -> Router {
+use super::{quantity::Quantity, recipe_planner, Day};
+use crate::error::{AppError, ConfigError};
+use crate::extract::{decode_base64, Cookies, Json};
+
+pub const DAY: Day = Day {
+    number: 7,
+    title: "Cookie recipes",
+    requires: &[],
+    routes: &[
+        "GET /7/decode",
+        "GET /7/bake",
+        "POST /7/recipe",
+        "POST /7/plan",
+    ],
+    router: |resources| get_routes(resources.recipe_cookies.clone()),
+};
+
+pub fn get_routes(cookies: Arc<RecipeCookies>) -> Router {
     Router::new()
         .route("/7/decode", get(decode))
         .route("/7/bake", get(bake))
+        .route("/7/recipe", post(recipe))
+        .route("/7/plan", post(plan))
+        .with_state(cookies)
+}
+
+const COOKIE_NAME: &str = "recipe";
+
+/// How the recipe cookie is protected from clients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CookieMode {
+    /// Plain base64, as the original puzzle sends it.
+    Plain,
+    /// Readable by clients but rejected if they change it.
+    Signed,
+    /// Encrypted and authenticated.
+    Private,
+}
+
+/// Reads and writes the recipe cookie. The keys sign or encrypt it, the first
+/// one for new cookies while the others still open the cookies they made.
+pub struct RecipeCookies {
+    mode: CookieMode,
+    keys: Vec<Key>,
+}
+
+impl RecipeCookies {
+    /// Reads `RECIPE_COOKIE_MODE=plain|signed|private` (plain by default) and
+    /// `RECIPE_COOKIE_KEYS`, comma separated base64 keys of at least 32 bytes, newest
+    /// first. Without keys a random one is used, invalidating cookies on restart.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let mode = match std::env::var("RECIPE_COOKIE_MODE").ok().as_deref() {
+            None | Some("plain") => CookieMode::Plain,
+            Some("signed") => CookieMode::Signed,
+            Some("private") => CookieMode::Private,
+            Some(mode) => return Err(ConfigError(format!("unknown recipe cookie mode {mode}"))),
+        };
+        let mut keys = match std::env::var("RECIPE_COOKIE_KEYS") {
+            Ok(keys) => keys
+                .split(',')
+                .map(str::trim)
+                .filter(|key| !key.is_empty())
+                .map(parse_key)
+                .collect::<Result<Vec<_>, _>>()?,
+            Err(_) => vec![],
+        };
+        if keys.is_empty() && mode != CookieMode::Plain {
+            warn!("RECIPE_COOKIE_KEYS is not set, recipe cookies will not survive a restart");
+            keys.push(Key::generate());
+        }
+
+        Ok(RecipeCookies { mode, keys })
+    }
+
+    /// The recipe cookie's value, checked with every key in turn.
+    fn open(&self, cookie: Cookie<'static>) -> Result<String, AppError> {
+        let opened = match self.mode {
+            CookieMode::Plain => return Ok(cookie.value().to_string()),
+            CookieMode::Signed => self
+                .keys
+                .iter()
+                .find_map(|key| CookieJar::new().signed(key).verify(cookie.clone())),
+            CookieMode::Private => self
+                .keys
+                .iter()
+                .find_map(|key| CookieJar::new().private(key).decrypt(cookie.clone())),
+        };
+
+        opened
+            .map(|cookie| cookie.value().to_string())
+            .ok_or_else(|| AppError::BadRequest("recipe cookie was tampered with".to_string()))
+    }
+
+    /// A recipe cookie holding `json`, protected with the newest key.
+    fn seal(&self, json: &str) -> Cookie<'static> {
+        let cookie = Cookie::build((COOKIE_NAME, general_purpose::STANDARD.encode(json)))
+            .path("/")
+            .http_only(true)
+            .build();
+        let mut jar = CookieJar::new();
+        match self.mode {
+            CookieMode::Plain => return cookie,
+            CookieMode::Signed => jar.signed_mut(&self.keys[0]).add(cookie),
+            CookieMode::Private => jar.private_mut(&self.keys[0]).add(cookie),
+        }
+
+        jar.get(COOKIE_NAME)
+            .cloned()
+            .expect("the cookie was just added")
+    }
+
+    fn set_cookie(&self, input: &RecipeInput) -> Result<[(HeaderName, String); 1], AppError> {
+        let json =
+            serde_json::to_string(input).map_err(|err| AppError::Internal(err.to_string()))?;
+
+        Ok([(SET_COOKIE, self.seal(&json).to_string())])
+    }
+
+    /// The recipe sent in the `recipe` cookie, as JSON.
+    fn decode(&self, cookies: &Cookies) -> Result<String, AppError> {
+        let encoded = self.open(cookies.get(COOKIE_NAME)?.clone())?;
+        let decoded = decode_base64(&encoded)?;
+
+        String::from_utf8(decoded)
+            .map_err(|err| AppError::BadRequest(format!("invalid utf-8: {err}")))
+    }
 }
 
-async fn decode(headers: HeaderMap) -> Result<String, StatusCode> {
+fn parse_key(key: &str) -> Result<Key, ConfigError> {
+    let invalid = |detail: String| ConfigError(format!("invalid recipe cookie key: {detail}"));
+    let bytes = general_purpose::STANDARD
+        .decode(key)
+        .map_err(|err| invalid(err.to_string()))?;
+
+    match bytes.len() {
+        0..=31 => Err(invalid(format!(
+            "{} bytes, at least 32 needed",
+            bytes.len()
+        ))),
+        32..=63 => Ok(Key::derive_from(&bytes)),
+        _ => Key::try_from(bytes.as_slice()).map_err(|err| invalid(err.to_string())),
+    }
+}
+
+async fn decode(
+    State(recipe_cookies): State<Arc<RecipeCookies>>,
+    cookies: Cookies,
+) -> Result<String, AppError> {
     info!("7 decode started");
-    let header_cookies = headers["cookie"].to_str().unwrap();
-    let c = Cookie::parse(header_cookies).unwrap();
-    let encoded = c.value();
-    let decoded = general_purpose::STANDARD.decode(encoded).unwrap();
-    let s = std::str::from_utf8(decoded.as_slice()).unwrap();
 
-    Ok(s.to_string())
+    recipe_cookies.decode(&cookies)
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
 struct RecipeInput {
     recipe: HashMap<String, Value>,
     pantry: HashMap<String, Value>,
@@ -35,44 +182,208 @@ struct RecipeOutput {
     pantry: HashMap<String, Value>,
 }
 
-async fn bake(headers: HeaderMap) -> Result<Json<RecipeOutput>, StatusCode> {
+/// Bakes as many cookies as the pantry allows and sends the recipe cookie back
+/// with what is left of the pantry. Amounts may be decimals with units, see
+/// [`Quantity`].
+async fn bake(
+    State(recipe_cookies): State<Arc<RecipeCookies>>,
+    cookies: Cookies,
+) -> Result<([(HeaderName, String); 1], Json<RecipeOutput>), AppError> {
     info!("7 bake started");
-    let header_cookies = headers["cookie"].to_str().unwrap();
-    let c = Cookie::parse(header_cookies).unwrap();
-    let encoded = c.value();
-    let decoded = general_purpose::STANDARD.decode(encoded).unwrap();
-    let json = std::str::from_utf8(decoded.as_slice()).unwrap();
-    let input: RecipeInput = serde_json::from_str(json).unwrap();
-    let mut cookies = u64::MAX;
+    let json = recipe_cookies.decode(&cookies)?;
+    let input: RecipeInput = serde_json::from_str(json.as_str())
+        .map_err(|err| AppError::BadRequest(format!("invalid recipe: {err}")))?;
 
+    let mut needs = vec![];
+    let mut times: Option<Decimal> = None;
     for (k, v) in input.recipe.iter() {
-        let v = v.as_u64().unwrap();
-
-        if v > 0 {
-            if let Some(pantry_value) = input.pantry.get(k) {
-                let pantry_value = pantry_value.as_u64().unwrap();
-                cookies = cookies.min(pantry_value / v);
-            } else {
-                cookies = 0;
-            }
+        let need = Quantity::from_json(k, v)?;
+        let have = match input.pantry.get(k) {
+            Some(pantry_value) => Some(Quantity::from_json(k, pantry_value)?),
+            None => None,
+        };
+
+        let fits = have
+            .unwrap_or_else(|| Quantity::zero(need.unit))
+            .fits(k, &need)?;
+        if let Some(n) = fits {
+            times = Some(times.map_or(n, |times| times.min(n)));
         }
+        needs.push((k, need, have));
     }
 
+    let cookies = match times {
+        Some(times) => times.to_u64().ok_or_else(|| {
+            AppError::Unprocessable(format!("{times} cookies are too many to bake"))
+        })?,
+        None => u64::MAX,
+    };
     let mut out = RecipeOutput {
         cookies,
         pantry: input.pantry,
     };
 
-    for (k, v) in input.recipe.iter() {
-        let v = v.as_u64().unwrap();
-
-        if v > 0 {
-            if let Some(pantry_value) = out.pantry.get_mut(k) {
-                let pantry_value_u = pantry_value.as_u64().unwrap();
-                *pantry_value = json!(pantry_value_u - cookies * v);
+    if let Some(times) = times {
+        for (k, need, have) in needs {
+            if let (Some(have), false) = (have, need.amount.is_zero()) {
+                out.pantry
+                    .insert(k.clone(), have.take(k, &need, times)?.to_json());
             }
         }
     }
 
-    Ok(out.into())
+    let updated = RecipeInput {
+        recipe: input.recipe,
+        pantry: out.pantry.clone(),
+    };
+
+    Ok((recipe_cookies.set_cookie(&updated)?, out.into()))
+}
+
+/// Issues a recipe cookie, the only way to get one when they are signed or
+/// encrypted.
+async fn recipe(
+    State(recipe_cookies): State<Arc<RecipeCookies>>,
+    Json(input): Json<RecipeInput>,
+) -> Result<([(HeaderName, String); 1], StatusCode), AppError> {
+    info!("7 recipe started");
+
+    Ok((recipe_cookies.set_cookie(&input)?, StatusCode::NO_CONTENT))
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct PlannedRecipe {
+    name: String,
+    ingredients: BTreeMap<String, u64>,
+    /// Worth of one batch when picking the mix, 1 by default.
+    #[serde(default = "default_value", alias = "priority")]
+    value: u64,
+    /// Batches wanted, for the shopping list.
+    target: Option<u64>,
+}
+
+fn default_value() -> u64 {
+    1
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct PlanInput {
+    recipes: Vec<PlannedRecipe>,
+    pantry: BTreeMap<String, u64>,
+}
+
+#[derive(serde::Serialize, Debug)]
+struct Plan {
+    batches: BTreeMap<String, u64>,
+    value: u128,
+    /// False when the search took too long, the mix then being the best found.
+    optimal: bool,
+    /// What is left once the mix is baked.
+    pantry: BTreeMap<String, u64>,
+    /// What to buy on top of the pantry to bake every recipe's target.
+    shopping_list: BTreeMap<String, u64>,
+}
+
+const MAX_PLANNED_RECIPES: usize = 16;
+const MAX_INGREDIENTS: usize = 64;
+
+/// Amounts of `ingredient` needed for `batches` of each recipe.
+fn total_need(recipes: &[PlannedRecipe], batches: impl Fn(usize) -> u64, ingredient: &str) -> u128 {
+    recipes
+        .iter()
+        .enumerate()
+        .map(|(i, recipe)| {
+            let need = recipe.ingredients.get(ingredient).copied().unwrap_or(0);
+            u128::from(need) * u128::from(batches(i))
+        })
+        .sum()
+}
+
+/// The mix of recipes worth the most that the pantry allows, what it leaves and
+/// what is missing to bake the targets.
+async fn plan(Json(input): Json<PlanInput>) -> Result<Json<Plan>, AppError> {
+    info!("7 plan started");
+    if input.recipes.is_empty() || input.recipes.len() > MAX_PLANNED_RECIPES {
+        return Err(AppError::Unprocessable(format!(
+            "between 1 and {MAX_PLANNED_RECIPES} recipes are needed"
+        )));
+    }
+    let mut names = HashSet::new();
+    for recipe in &input.recipes {
+        if !names.insert(&recipe.name) {
+            return Err(AppError::Unprocessable(format!(
+                "recipe '{}' is given twice",
+                recipe.name
+            )));
+        }
+        if recipe.ingredients.values().all(|&need| need == 0) {
+            return Err(AppError::Unprocessable(format!(
+                "recipe '{}' needs no ingredient",
+                recipe.name
+            )));
+        }
+    }
+
+    let ingredients = input
+        .recipes
+        .iter()
+        .flat_map(|recipe| recipe.ingredients.keys())
+        .chain(input.pantry.keys())
+        .cloned()
+        .collect::<BTreeSet<_>>();
+    if ingredients.len() > MAX_INGREDIENTS {
+        return Err(AppError::Unprocessable(format!(
+            "at most {MAX_INGREDIENTS} ingredients can be planned"
+        )));
+    }
+    let have = |ingredient: &str| input.pantry.get(ingredient).copied().unwrap_or(0);
+    let planned = input
+        .recipes
+        .iter()
+        .map(|recipe| recipe_planner::Recipe {
+            needs: ingredients
+                .iter()
+                .map(|ingredient| recipe.ingredients.get(ingredient).copied().unwrap_or(0))
+                .collect(),
+            value: recipe.value,
+        })
+        .collect::<Vec<_>>();
+    let pantry = ingredients.iter().map(|i| have(i)).collect::<Vec<_>>();
+    let mix = tokio::task::spawn_blocking(move || {
+        recipe_planner::plan(&planned, &pantry, recipe_planner::Budget::default())
+    })
+    .await
+    .map_err(|err| AppError::Internal(err.to_string()))?;
+
+    let mut leftover = BTreeMap::new();
+    let mut shopping_list = BTreeMap::new();
+    for ingredient in &ingredients {
+        let used = total_need(&input.recipes, |i| mix.batches[i], ingredient);
+        leftover.insert(ingredient.clone(), have(ingredient) - used as u64);
+
+        let wanted = total_need(
+            &input.recipes,
+            |i| input.recipes[i].target.unwrap_or(0),
+            ingredient,
+        );
+        let missing = wanted.saturating_sub(u128::from(have(ingredient)));
+        if missing > 0 {
+            let missing = u64::try_from(missing)
+                .map_err(|_| AppError::Unprocessable(format!("too much {ingredient} is needed")))?;
+            shopping_list.insert(ingredient.clone(), missing);
+        }
+    }
+
+    Ok(Json(Plan {
+        batches: input
+            .recipes
+            .iter()
+            .zip(mix.batches)
+            .map(|(recipe, batches)| (recipe.name.clone(), batches))
+            .collect(),
+        value: mix.value,
+        optimal: mix.optimal,
+        pantry: leftover,
+        shopping_list,
+    }))
 }
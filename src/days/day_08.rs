@@ -1,49 +1,46 @@
-use axum::{extract::Path, routing::get, Router};
+use axum::{extract::State, routing::get, Router};
 use log::info;
-use reqwest::StatusCode;
-
-pub fn get_routes() -> Router {
+use std::sync::Arc;
+
+use super::{pokemon_source::PokemonSource, Day};
+use crate::error::AppError;
+use crate::extract::Path;
+
+pub const DAY: Day = Day {
+    number: 8,
+    title: "PokéPhysics",
+    requires: &[],
+    routes: &[
+        "GET /8/weight/:pokedex_number",
+        "GET /8/drop/:pokedex_number",
+    ],
+    router: |resources| get_routes(resources.pokemon_source.clone()),
+};
+
+pub fn get_routes(source: Arc<dyn PokemonSource>) -> Router {
     Router::new()
         .route("/8/weight/:pokedex_number", get(weight))
         .route("/8/drop/:pokedex_number", get(drop))
+        .with_state(source)
 }
 
-#[derive(serde::Deserialize, Debug)]
-struct PokeApi {
-    weight: f64,
-}
-
-async fn weight(Path(pokedex_number): Path<String>) -> Result<String, StatusCode> {
+async fn weight(
+    Path(pokedex_number): Path<String>,
+    State(source): State<Arc<dyn PokemonSource>>,
+) -> Result<String, AppError> {
     info!("8 weight started");
-    let body = reqwest::get(format!(
-        "https://pokeapi.co/api/v2/pokemon/{}",
-        pokedex_number
-    ))
-    .await
-    .map_err(|_err| StatusCode::INTERNAL_SERVER_ERROR)?
-    .text()
-    .await
-    .map_err(|_err| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let mut input: PokeApi = serde_json::from_str(body.as_str()).unwrap();
+    let mut input = source.get(&pokedex_number).await?;
     input.weight /= 10.;
 
     Ok(input.weight.to_string())
 }
 
-async fn drop(Path(pokedex_number): Path<String>) -> Result<String, StatusCode> {
+async fn drop(
+    Path(pokedex_number): Path<String>,
+    State(source): State<Arc<dyn PokemonSource>>,
+) -> Result<String, AppError> {
     info!("8 drop started");
-    let body = reqwest::get(format!(
-        "https://pokeapi.co/api/v2/pokemon/{}",
-        pokedex_number
-    ))
-    .await
-    .map_err(|_err| StatusCode::INTERNAL_SERVER_ERROR)?
-    .text()
-    .await
-    .map_err(|_err| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let input: PokeApi = serde_json::from_str(body.as_str()).unwrap();
+    let input = source.get(&pokedex_number).await?;
     let m = input.weight / 10.;
     let a = 9.825;
     let x = 10.;
@@ -1,31 +1,47 @@
 use axum::{extract::Multipart, routing::post, Router};
-use image::{io::Reader as ImageReader, GenericImageView, Pixel};
-use itertools::Itertools;
+use image::{io::Reader as ImageReader, GenericImageView};
 use log::info;
-use reqwest::StatusCode;
 use std::io::Cursor;
 use tower_http::services::ServeDir;
 
+use super::Day;
+use crate::error::AppError;
+
+pub const DAY: Day = Day {
+    number: 11,
+    title: "Red pixels",
+    requires: &[],
+    routes: &["POST /11/red_pixels", "GET /11/assets/*file"],
+    router: |_| get_routes(),
+};
+
 pub fn get_routes() -> Router {
     Router::new()
         .nest_service("/11/assets", ServeDir::new("assets"))
         .route("/11/red_pixels", post(red_pixels))
 }
 
-async fn red_pixels(mut multipart: Multipart) -> Result<String, StatusCode> {
+async fn red_pixels(mut multipart: Multipart) -> Result<String, AppError> {
     info!("11 started");
     let mut out = 0;
 
-    while let Some(field) = multipart.next_field().await.unwrap() {
-        let data = field.bytes().await.unwrap();
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|err| AppError::BadRequest(format!("invalid multipart body: {err}")))?
+    {
+        let data = field
+            .bytes()
+            .await
+            .map_err(|err| AppError::BadRequest(format!("invalid multipart field: {err}")))?;
         let img = ImageReader::new(Cursor::new(data))
             .with_guessed_format()
-            .map_err(|_err| StatusCode::INTERNAL_SERVER_ERROR)?
+            .map_err(|err| AppError::Internal(err.to_string()))?
             .decode()
-            .map_err(|_err| StatusCode::INTERNAL_SERVER_ERROR)?;
+            .map_err(|err| AppError::Unprocessable(format!("invalid image: {err}")))?;
 
         for (_, _, rgba) in img.pixels() {
-            let (&r, &g, &b, _) = rgba.channels().into_iter().collect_tuple().unwrap();
+            let [r, g, b, _] = rgba.0;
 
             if r.saturating_sub(g).saturating_sub(b) > 0 {
                 out += 1;
@@ -1,63 +1,158 @@
 use axum::{
-    extract::{Path, State},
-    routing::{get, post},
-    Json, Router,
+    extract::State,
+    http::StatusCode,
+    routing::{delete, get, post},
+    Router,
 };
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, SecondsFormat, Timelike, Utc};
 use log::info;
 use num_traits::PrimInt;
-use reqwest::StatusCode;
-use std::{collections::HashMap, sync::Arc};
-use std::{sync::Mutex, time::SystemTime};
+use std::{
+    collections::BTreeMap,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use super::time_store::{Saved, TimeStore};
+use super::Day;
+use crate::clock::Clock;
+use crate::error::{AppError, ItemError};
+use crate::extract::{Json, Path, Query};
+use crate::tz::TimeZone;
+
+pub const DAY: Day = Day {
+    number: 12,
+    title: "Timekeeper and ULIDs",
+    requires: &[],
+    routes: &[
+        "POST /12/save/:string",
+        "GET /12/load/:string",
+        "GET /12/keys",
+        "DELETE /12/keys/:string",
+        "POST /12/ulids",
+        "POST /12/ulids/:weekday",
+        "POST /12/ulids/generate",
+        "POST /12/ulids/from_uuids",
+        "POST /12/ulids/decode",
+        "POST /12/ulids/sort",
+        "POST /12/ulids/stats",
+    ],
+    router: |resources| get_routes(resources.time_store.clone(), resources.clock.clone()),
+};
 
-pub fn get_routes() -> Router {
+pub fn get_routes(store: Arc<dyn TimeStore>, clock: Arc<dyn Clock>) -> Router {
     Router::new()
         .route("/12/save/:string", post(save_string))
         .route("/12/load/:string", get(load_string))
-        .with_state(Arc::new(AppState {
-            data: Arc::new(Mutex::new(HashMap::new())),
-        }))
+        .route("/12/keys", get(list_keys))
+        .route("/12/keys/:string", delete(delete_key))
         .route("/12/ulids", post(ulids))
         .route("/12/ulids/:weekday", post(ulids_weekday))
+        .route("/12/ulids/generate", post(generate_ulids))
+        .route("/12/ulids/from_uuids", post(ulids_from_uuids))
+        .route("/12/ulids/decode", post(decode_ulids))
+        .route("/12/ulids/sort", post(sort_ulids))
+        .route("/12/ulids/stats", post(ulids_stats))
+        .with_state(TimeState { store, clock })
+}
+
+#[derive(Clone)]
+pub struct TimeState {
+    pub store: Arc<dyn TimeStore>,
+    pub clock: Arc<dyn Clock>,
 }
 
-struct AppState {
-    data: Arc<Mutex<HashMap<String, SystemTime>>>,
+#[derive(serde::Deserialize, Debug, Default)]
+struct SaveParams {
+    /// Seconds after which the string is forgotten, never by default.
+    ttl: Option<u64>,
+}
+
+fn not_saved(s: &str) -> AppError {
+    AppError::NotFound(format!("'{s}' was never saved or has expired"))
 }
 
 async fn save_string(
     Path(s): Path<String>,
-    State(state): State<Arc<AppState>>,
-) -> Result<(), StatusCode> {
+    Query(params): Query<SaveParams>,
+    State(state): State<TimeState>,
+) -> Result<(), AppError> {
     info!("12 save started");
-    let mut data = state.data.lock().expect("mutex was poisoned");
-    data.insert(s.clone(), SystemTime::now());
+    let saved = Saved::new(state.clock.now(), params.ttl.map(Duration::from_secs))?;
+    state.store.save(&s, saved).await?;
 
     Ok(())
 }
 
 async fn load_string(
     Path(s): Path<String>,
-    State(state): State<Arc<AppState>>,
-) -> Result<String, StatusCode> {
+    State(state): State<TimeState>,
+) -> Result<String, AppError> {
     info!("12 load started");
-    let data = state.data.lock().expect("mutex was poisoned");
-    let now = SystemTime::now();
-    let t = data.get(&s).unwrap();
+    let now = state.clock.now();
+    let t = state
+        .store
+        .load(&s, now)
+        .await?
+        .ok_or_else(|| not_saved(&s))?;
+    let elapsed = now.duration_since(t).unwrap_or_default();
+
+    Ok(elapsed.as_secs().to_string())
+}
+
+fn to_rfc3339(t: SystemTime) -> String {
+    DateTime::<Utc>::from(t).to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+#[derive(serde::Serialize, Debug)]
+struct SavedKey {
+    key: String,
+    saved_at: String,
+    expires_at: Option<String>,
+}
+
+async fn list_keys(State(state): State<TimeState>) -> Result<Json<Vec<SavedKey>>, AppError> {
+    info!("12 keys started");
+    let keys = state.store.keys(state.clock.now()).await?;
+
+    Ok(keys
+        .into_iter()
+        .map(|(key, saved)| SavedKey {
+            key,
+            saved_at: to_rfc3339(saved.saved_at),
+            expires_at: saved.expires_at.map(to_rfc3339),
+        })
+        .collect::<Vec<_>>()
+        .into())
+}
+
+async fn delete_key(
+    Path(s): Path<String>,
+    State(state): State<TimeState>,
+) -> Result<StatusCode, AppError> {
+    info!("12 delete key started");
+    if !state.store.delete(&s, state.clock.now()).await? {
+        return Err(not_saved(&s));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
 
-    Ok(now.duration_since(*t).unwrap().as_secs().to_string())
+fn parse_ulid(ulid_string: &str) -> Result<ulid::Ulid, AppError> {
+    ulid::Ulid::from_string(ulid_string)
+        .map_err(|err| AppError::Unprocessable(format!("invalid ulid '{ulid_string}': {err}")))
 }
 
-async fn ulids(Json(ulid_strings): Json<Vec<String>>) -> Result<String, StatusCode> {
+async fn ulids(Json(ulid_strings): Json<Vec<String>>) -> Result<String, AppError> {
     info!("12 ulids started");
     let uuid_strings = ulid_strings
         .into_iter()
         .map(|ulid_string| {
-            let ulid_bytes = ulid::Ulid::from_string(&ulid_string).unwrap().to_bytes();
-            uuid::Uuid::from_bytes(ulid_bytes).to_string()
+            let ulid_bytes = parse_ulid(&ulid_string)?.to_bytes();
+            Ok(uuid::Uuid::from_bytes(ulid_bytes).to_string())
         })
         .rev()
-        .collect::<Vec<String>>();
+        .collect::<Result<Vec<String>, AppError>>()?;
 
     Ok(format!("{:?}", uuid_strings))
 }
@@ -65,12 +160,12 @@ async fn ulids(Json(ulid_strings): Json<Vec<String>>) -> Result<String, StatusCo
 #[derive(serde::Serialize, Debug, Clone, Default)]
 struct UlidStats {
     #[serde(rename = "christmas eve")]
-    christmas_eve: u16,
-    weekday: u16,
+    christmas_eve: usize,
+    weekday: usize,
     #[serde(rename = "in the future")]
-    in_the_future: u16,
+    in_the_future: usize,
     #[serde(rename = "LSB is 1")]
-    lsb_is_1: u16,
+    lsb_is_1: usize,
 }
 
 pub fn get_lsb<N: PrimInt>(n: N) -> N {
@@ -79,21 +174,379 @@ pub fn get_lsb<N: PrimInt>(n: N) -> N {
 
 async fn ulids_weekday(
     Path(expected_weekday): Path<u8>,
+    State(state): State<TimeState>,
     Json(ulid_strings): Json<Vec<String>>,
-) -> Result<Json<UlidStats>, StatusCode> {
+) -> Result<Json<UlidStats>, AppError> {
     info!("12 ulids weekday started");
-    let mut ulid_stats = UlidStats::default();
-    for ulid_string in ulid_strings.into_iter() {
-        let ulid = ulid::Ulid::from_string(&ulid_string).unwrap();
+    let ulids = ulid_strings
+        .iter()
+        .map(|ulid_string| parse_ulid(ulid_string))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let predicates = [
+        Predicate::MonthDay {
+            month: Some(12),
+            day: Some(24),
+        },
+        Predicate::Weekdays {
+            weekdays: vec![expected_weekday],
+        },
+        Predicate::InTheFuture,
+        Predicate::Bit { bit: 0, set: true },
+    ];
+    let now = state.clock.now();
+    let mut counts = [0; 4];
+    for ulid in &ulids {
+        let facts = UlidFacts::new(ulid, &TimeZone::utc());
+        for (count, predicate) in counts.iter_mut().zip(&predicates) {
+            *count += predicate.matches(&facts, now) as usize;
+        }
+    }
+    let [christmas_eve, weekday, in_the_future, lsb_is_1] = counts;
+
+    Ok(UlidStats {
+        christmas_eve,
+        weekday,
+        in_the_future,
+        lsb_is_1,
+    }
+    .into())
+}
+
+/// A condition on the time or randomness of a ULID, dates in the requested time zone.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Predicate {
+    /// Date between `from` and `to`, both included and optional.
+    DateRange {
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    },
+    /// Month (1 to 12) and/or day of the month (1 to 31).
+    MonthDay {
+        month: Option<u32>,
+        day: Option<u32>,
+    },
+    /// Days from Monday, as in `/12/ulids/:weekday`.
+    Weekdays { weekdays: Vec<u8> },
+    /// Bit `bit` of the 80 random bits, 0 being the least significant.
+    Bit {
+        bit: u8,
+        #[serde(default = "default_set")]
+        set: bool,
+    },
+    /// Later than the server's clock.
+    InTheFuture,
+}
+
+fn default_set() -> bool {
+    true
+}
+
+/// What predicates look at, computed once per ULID.
+struct UlidFacts {
+    datetime: SystemTime,
+    local: DateTime<FixedOffset>,
+    random: u128,
+}
+
+impl UlidFacts {
+    fn new(ulid: &ulid::Ulid, tz: &TimeZone) -> Self {
+        UlidFacts {
+            datetime: ulid.datetime(),
+            local: tz.to_local(ulid.datetime().into()),
+            random: ulid.random(),
+        }
+    }
+}
+
+impl Predicate {
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            Predicate::MonthDay { month, day } => {
+                if month.is_some_and(|month| !(1..=12).contains(&month)) {
+                    return Err("month must be between 1 and 12".to_string());
+                }
+                if day.is_some_and(|day| !(1..=31).contains(&day)) {
+                    return Err("day must be between 1 and 31".to_string());
+                }
+            }
+            Predicate::Weekdays { weekdays } => {
+                if weekdays.iter().any(|weekday| *weekday > 6) {
+                    return Err("weekdays must be between 0 (Monday) and 6".to_string());
+                }
+            }
+            Predicate::Bit { bit, .. } => {
+                if *bit >= 80 {
+                    return Err("bit must be between 0 and 79".to_string());
+                }
+            }
+            Predicate::DateRange { .. } | Predicate::InTheFuture => {}
+        }
+
+        Ok(())
+    }
+
+    fn matches(&self, facts: &UlidFacts, now: SystemTime) -> bool {
+        match self {
+            Predicate::DateRange { from, to } => {
+                let date = facts.local.date_naive();
+                from.is_none_or(|from| from <= date) && to.is_none_or(|to| date <= to)
+            }
+            Predicate::MonthDay { month, day } => {
+                month.is_none_or(|month| facts.local.month() == month)
+                    && day.is_none_or(|day| facts.local.day() == day)
+            }
+            Predicate::Weekdays { weekdays } => {
+                let weekday = facts.local.weekday().num_days_from_monday() as u8;
+                weekdays.contains(&weekday)
+            }
+            Predicate::Bit { bit, set } => (get_lsb(facts.random >> bit) == 1) == *set,
+            Predicate::InTheFuture => facts.datetime > now,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct NamedPredicate {
+    name: String,
+    #[serde(flatten)]
+    predicate: Predicate,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct StatsRequest {
+    ulids: Vec<String>,
+    /// IANA name such as `Europe/Paris`, UTC by default.
+    timezone: Option<String>,
+    #[serde(default)]
+    predicates: Vec<NamedPredicate>,
+}
+
+#[derive(serde::Serialize, Debug)]
+struct PredicateCount {
+    name: String,
+    count: usize,
+}
+
+#[derive(serde::Serialize, Debug)]
+struct StatsReport {
+    timezone: String,
+    /// Number of valid ULIDs.
+    total: usize,
+    counts: Vec<PredicateCount>,
+    /// ULIDs per local date.
+    days: BTreeMap<NaiveDate, usize>,
+    /// ULIDs per local hour of the day.
+    hours: [usize; 24],
+    errors: Vec<ItemError>,
+}
+
+async fn ulids_stats(
+    State(state): State<TimeState>,
+    Json(request): Json<StatsRequest>,
+) -> Result<Json<StatsReport>, AppError> {
+    info!("12 ulids stats started");
+    let tz = match &request.timezone {
+        Some(name) => TimeZone::named(name)?,
+        None => TimeZone::utc(),
+    };
+    for named in &request.predicates {
+        named.predicate.validate().map_err(|err| {
+            AppError::BadRequest(format!("invalid predicate '{}': {err}", named.name))
+        })?;
+    }
+
+    let now = state.clock.now();
+    let mut report = StatsReport {
+        timezone: tz.name().to_string(),
+        total: 0,
+        counts: request
+            .predicates
+            .iter()
+            .map(|named| PredicateCount {
+                name: named.name.clone(),
+                count: 0,
+            })
+            .collect(),
+        days: BTreeMap::new(),
+        hours: [0; 24],
+        errors: vec![],
+    };
+    for (index, ulid_string) in request.ulids.iter().enumerate() {
+        let ulid = match parse_ulid(ulid_string) {
+            Ok(ulid) => ulid,
+            Err(err) => {
+                report.errors.push(ItemError {
+                    index,
+                    detail: err.detail(),
+                });
+                continue;
+            }
+        };
+
+        let facts = UlidFacts::new(&ulid, &tz);
+        report.total += 1;
+        *report.days.entry(facts.local.date_naive()).or_insert(0) += 1;
+        report.hours[facts.local.hour() as usize] += 1;
+        for (count, named) in report.counts.iter_mut().zip(&request.predicates) {
+            count.count += named.predicate.matches(&facts, now) as usize;
+        }
+    }
+
+    Ok(report.into())
+}
+
+/// What went right and wrong in a batch of ULIDs: invalid items are reported in
+/// `errors` instead of failing the whole request.
+#[derive(serde::Serialize, Debug)]
+struct ItemsReport<T> {
+    items: Vec<T>,
+    errors: Vec<ItemError>,
+}
+
+impl<T> ItemsReport<T> {
+    /// Keeps the items `f` accepts, the others becoming errors at their index.
+    fn from_inputs(
+        inputs: Vec<String>,
+        mut f: impl FnMut(usize, &str) -> Result<T, AppError>,
+    ) -> Self {
+        let mut report = ItemsReport {
+            items: vec![],
+            errors: vec![],
+        };
+        for (index, input) in inputs.iter().enumerate() {
+            match f(index, input) {
+                Ok(item) => report.items.push(item),
+                Err(err) => report.errors.push(ItemError {
+                    index,
+                    detail: err.detail(),
+                }),
+            }
+        }
+
+        report
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct GenerateParams {
+    count: usize,
+}
+
+const MAX_GENERATE: usize = 1000;
+
+/// ULIDs of the current millisecond, strictly increasing even within it.
+async fn generate_ulids(
+    Query(params): Query<GenerateParams>,
+    State(state): State<TimeState>,
+) -> Result<Json<Vec<String>>, AppError> {
+    info!("12 ulids generate started");
+    if !(1..=MAX_GENERATE).contains(&params.count) {
+        return Err(AppError::BadRequest(format!(
+            "count must be between 1 and {MAX_GENERATE}"
+        )));
+    }
+
+    let now = state.clock.now();
+    let mut generator = ulid::Generator::new();
+    let ulids = (0..params.count)
+        .map(|_| {
+            generator
+                .generate_from_datetime(now)
+                .map(|ulid| ulid.to_string())
+                .map_err(|err| AppError::Internal(err.to_string()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(ulids.into())
+}
+
+#[derive(serde::Serialize, Debug)]
+struct Converted {
+    index: usize,
+    uuid: String,
+    ulid: String,
+}
+
+async fn ulids_from_uuids(Json(uuid_strings): Json<Vec<String>>) -> Json<ItemsReport<Converted>> {
+    info!("12 ulids from uuids started");
+    let report = ItemsReport::from_inputs(uuid_strings, |index, uuid_string| {
+        let uuid = uuid::Uuid::parse_str(uuid_string).map_err(|err| {
+            AppError::Unprocessable(format!("invalid uuid '{uuid_string}': {err}"))
+        })?;
+
+        Ok(Converted {
+            index,
+            uuid: uuid.to_string(),
+            ulid: ulid::Ulid::from(uuid).to_string(),
+        })
+    });
+
+    report.into()
+}
+
+#[derive(serde::Serialize, Debug)]
+struct Decoded {
+    index: usize,
+    ulid: String,
+    timestamp: String,
+    timestamp_ms: u64,
+    /// The 80 random bits, in hexadecimal.
+    randomness: String,
+    /// Days from Monday, as in `/12/ulids/:weekday`.
+    weekday: u8,
+}
+
+async fn decode_ulids(Json(ulid_strings): Json<Vec<String>>) -> Json<ItemsReport<Decoded>> {
+    info!("12 ulids decode started");
+    let report = ItemsReport::from_inputs(ulid_strings, |index, ulid_string| {
+        let ulid = parse_ulid(ulid_string)?;
         let datetime: DateTime<Utc> = ulid.datetime().into();
-        let day_month = datetime.format("%d-%m").to_string();
-        let weekday = datetime.format("%u").to_string().parse::<u8>().unwrap() - 1;
 
-        ulid_stats.christmas_eve += (day_month == "24-12") as u16;
-        ulid_stats.weekday += (weekday == expected_weekday) as u16;
-        ulid_stats.in_the_future += (ulid.datetime() > SystemTime::now()) as u16;
-        ulid_stats.lsb_is_1 += (get_lsb(ulid.to_bytes()[15]) == 1) as u16;
+        Ok(Decoded {
+            index,
+            ulid: ulid.to_string(),
+            timestamp: to_rfc3339(ulid.datetime()),
+            timestamp_ms: ulid.timestamp_ms(),
+            randomness: format!("{:020x}", ulid.random()),
+            weekday: datetime.weekday().num_days_from_monday() as u8,
+        })
+    });
+
+    report.into()
+}
+
+#[derive(serde::Deserialize, Debug, Default)]
+struct SortParams {
+    /// Keeps a single copy of repeated ULIDs.
+    #[serde(default)]
+    dedup: bool,
+    /// Newest first instead of oldest first.
+    #[serde(default)]
+    reverse: bool,
+}
+
+/// Valid ULIDs in chronological order, canonically encoded.
+async fn sort_ulids(
+    Query(params): Query<SortParams>,
+    Json(ulid_strings): Json<Vec<String>>,
+) -> Json<ItemsReport<String>> {
+    info!("12 ulids sort started");
+    let report = ItemsReport::from_inputs(ulid_strings, |_, ulid_string| parse_ulid(ulid_string));
+
+    let mut ulids = report.items;
+    ulids.sort_unstable();
+    if params.dedup {
+        ulids.dedup();
+    }
+    if params.reverse {
+        ulids.reverse();
     }
 
-    Ok(ulid_stats.into())
+    ItemsReport {
+        items: ulids.iter().map(ulid::Ulid::to_string).collect(),
+        errors: report.errors,
+    }
+    .into()
 }
@@ -1,6 +1,17 @@
-use axum::{routing::post, Json, Router};
+use axum::{routing::post, Router};
 use log::info;
-use reqwest::StatusCode;
+
+use super::Day;
+use crate::error::AppError;
+use crate::extract::Json;
+
+pub const DAY: Day = Day {
+    number: 14,
+    title: "Reindeering HTML",
+    requires: &[],
+    routes: &["POST /14/unsafe", "POST /14/safe"],
+    router: |_| get_routes(),
+};
 
 pub fn get_routes() -> Router {
     Router::new()
@@ -13,7 +24,7 @@ struct HtmlContent {
     content: String,
 }
 
-async fn html_unsafe(Json(body): Json<HtmlContent>) -> Result<String, StatusCode> {
+async fn html_unsafe(Json(body): Json<HtmlContent>) -> Result<String, AppError> {
     info!("14 html unsafe started");
 
     Ok(format!(
@@ -27,11 +38,10 @@ async fn html_unsafe(Json(body): Json<HtmlContent>) -> Result<String, StatusCode
   </body>
 </html>",
         body.content
-    )
-    .to_string())
+    ))
 }
 
-async fn html_safe(Json(body): Json<HtmlContent>) -> Result<String, StatusCode> {
+async fn html_safe(Json(body): Json<HtmlContent>) -> Result<String, AppError> {
     info!("14 html safe started");
 
     Ok(format!(
@@ -45,6 +55,5 @@ async fn html_safe(Json(body): Json<HtmlContent>) -> Result<String, StatusCode>
   </body>
 </html>",
         html_escape::encode_double_quoted_attribute(body.content.as_str())
-    )
-    .to_string())
+    ))
 }
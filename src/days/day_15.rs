@@ -1,9 +1,20 @@
-use axum::{routing::post, Json, Router};
+use axum::{http::StatusCode, routing::post, Router};
 use log::info;
 use regex::Regex;
-use reqwest::StatusCode;
 use unicode_segmentation::UnicodeSegmentation;
 
+use super::Day;
+use crate::error::AppError;
+use crate::extract::Json;
+
+pub const DAY: Day = Day {
+    number: 15,
+    title: "Password validator",
+    requires: &[],
+    routes: &["POST /15/nice", "POST /15/game"],
+    router: |_| get_routes(),
+};
+
 pub fn get_routes() -> Router {
     Router::new()
         .route("/15/nice", post(nice))
@@ -15,14 +26,14 @@ struct Password {
     input: String,
 }
 
-async fn nice(Json(password): Json<Password>) -> Result<(StatusCode, String), StatusCode> {
+async fn nice(Json(password): Json<Password>) -> Result<(StatusCode, String), AppError> {
     info!("15 nice started");
 
     let re_vowels = Regex::new(r"[aeiouy]").unwrap();
     let re_doubles = Regex::new(r"(ab|cd|pq|xy)").unwrap();
     if re_vowels.find_iter(password.input.as_str()).count() < 3
         || !has_two_consecutive_chars(password.input.as_str())
-        || re_doubles.find(password.input.as_str()) != None
+        || re_doubles.is_match(password.input.as_str())
     {
         return Ok((
             StatusCode::BAD_REQUEST,
@@ -33,14 +44,14 @@ async fn nice(Json(password): Json<Password>) -> Result<(StatusCode, String), St
     Ok((StatusCode::OK, "{\"result\":\"nice\"}".to_string()))
 }
 
-async fn game(Json(password): Json<Password>) -> Result<String, (StatusCode, String)> {
+async fn game(Json(password): Json<Password>) -> Result<(StatusCode, String), AppError> {
     info!("15 game started");
 
     let s = password.input;
 
     // Rule 1: must be at least 8 characters long
     if s.len() < 8 {
-        return Err((
+        return Ok((
             StatusCode::BAD_REQUEST,
             "{\"result\":\"naughty\", \"reason\":\"8 chars\"}".to_string(),
         ));
@@ -51,11 +62,11 @@ async fn game(Json(password): Json<Password>) -> Result<String, (StatusCode, Str
     let re_lower = Regex::new(r"[a-z]").unwrap();
     let re_digits = Regex::new(r"[\d]").unwrap();
 
-    if re_upper.find(s.as_str()) == None
-        || re_lower.find(s.as_str()) == None
-        || re_digits.find(s.as_str()) == None
+    if !re_upper.is_match(s.as_str())
+        || !re_lower.is_match(s.as_str())
+        || !re_digits.is_match(s.as_str())
     {
-        return Err((
+        return Ok((
             StatusCode::BAD_REQUEST,
             "{\"result\":\"naughty\", \"reason\":\"more types of chars\"}".to_string(),
         ));
@@ -63,7 +74,7 @@ async fn game(Json(password): Json<Password>) -> Result<String, (StatusCode, Str
 
     // Rule 3: must contain at least 5 digits
     if re_digits.find_iter(s.as_str()).count() < 5 {
-        return Err((
+        return Ok((
             StatusCode::BAD_REQUEST,
             "{\"result\":\"naughty\", \"reason\":\"55555\"}".to_string(),
         ));
@@ -71,13 +82,14 @@ async fn game(Json(password): Json<Password>) -> Result<String, (StatusCode, Str
 
     // Rule 4: all integers (sequences of consecutive digits) in the string must add up to 2023
     let re_numbers = Regex::new(r"[\d]+").unwrap();
-    let mut sum = 0;
-    for number in re_numbers.find_iter(s.as_str()) {
-        sum += number.as_str().parse::<u32>().unwrap();
-    }
+    let sum = re_numbers
+        .find_iter(s.as_str())
+        .try_fold(0u32, |acc, number| {
+            acc.checked_add(number.as_str().parse::<u32>().ok()?)
+        });
 
-    if sum != 2023 {
-        return Err((
+    if sum != Some(2023) {
+        return Ok((
             StatusCode::BAD_REQUEST,
             "{\"result\":\"naughty\", \"reason\":\"math is hard\"}".to_string(),
         ));
@@ -96,7 +108,7 @@ async fn game(Json(password): Json<Password>) -> Result<String, (StatusCode, Str
         }
     }
     if joy != (true, true, true) || re_joy.find_iter(s.as_str()).count() != 3 {
-        return Err((
+        return Ok((
             StatusCode::NOT_ACCEPTABLE,
             "{\"result\":\"naughty\", \"reason\":\"not joyful enough\"}".to_string(),
         ));
@@ -118,7 +130,7 @@ async fn game(Json(password): Json<Password>) -> Result<String, (StatusCode, Str
         }
     }
     if !rule6 {
-        return Err((
+        return Ok((
             StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS,
             "{\"result\":\"naughty\", \"reason\":\"illegal: no sandwich\"}".to_string(),
         ));
@@ -135,7 +147,7 @@ async fn game(Json(password): Json<Password>) -> Result<String, (StatusCode, Str
         }
     }
     if !rule7 {
-        return Err((
+        return Ok((
             StatusCode::RANGE_NOT_SATISFIABLE,
             "{\"result\":\"naughty\", \"reason\":\"outranged\"}".to_string(),
         ));
@@ -152,7 +164,7 @@ async fn game(Json(password): Json<Password>) -> Result<String, (StatusCode, Str
         }
     }
     if !rule8 {
-        return Err((
+        return Ok((
             StatusCode::UPGRADE_REQUIRED,
             "{\"result\":\"naughty\", \"reason\":\"😳\"}".to_string(),
         ));
@@ -160,19 +172,24 @@ async fn game(Json(password): Json<Password>) -> Result<String, (StatusCode, Str
 
     // Rule 9: the hexadecimal representation of the sha256 hash of the string must end with an a
     let hash = sha256::digest(s.to_string());
-    if hash.chars().last().unwrap() != 'a' {
-        return Err((
+    if !hash.ends_with('a') {
+        return Ok((
             StatusCode::IM_A_TEAPOT,
             "{\"result\":\"naughty\", \"reason\":\"not a coffee brewer\"}".to_string(),
         ));
     }
 
-    Ok("{\"result\":\"nice\", \"reason\":\"that's a nice password\"}".to_string())
+    Ok((
+        StatusCode::OK,
+        "{\"result\":\"nice\", \"reason\":\"that's a nice password\"}".to_string(),
+    ))
 }
 
 fn has_two_consecutive_chars(s: &str) -> bool {
     let mut chars = s.chars();
-    let mut prev = chars.next().unwrap();
+    let Some(mut prev) = chars.next() else {
+        return false;
+    };
     for c in chars {
         if c == prev && c.is_alphabetic() {
             return true;
@@ -1,127 +1,358 @@
 use axum::{
-    extract::{Path, State},
+    body::StreamBody,
+    extract::State,
+    http::header::CONTENT_TYPE,
+    response::{IntoResponse, Response},
     routing::{get, post},
-    Json, Router,
+    Router,
 };
+use futures::{stream, StreamExt};
 use log::info;
-use reqwest::StatusCode;
-use sqlx::PgPool;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
+use super::day_13::{reset, DbState, InsertParams};
+use super::order_store::{
+    ExportRow, GiftTotalRow, InsertReport, Order, OrderFilter, OrderRegions, OrderStore, Region,
+    RegionGiftRow, RegionsTopRow, RegionsTotalRow, Ties,
+};
+use super::Day;
+use crate::error::AppError;
+use crate::extract::{Batch, Json, Path, Query};
+use crate::stats::parse_percentiles;
 
-use super::day_13::{orders, reset, DbState};
+pub const DAY: Day = Day {
+    number: 18,
+    title: "Regions and top gifts",
+    requires: &[],
+    routes: &[
+        "POST /18/reset",
+        "POST /18/orders",
+        "POST /18/regions",
+        "GET /18/regions/total",
+        "GET /18/regions/top_list/:limit",
+        "GET /18/analytics/gifts",
+        "GET /18/analytics/regions",
+        "GET /18/analytics/pivot",
+        "GET /18/analytics/percentiles",
+        "GET /18/export",
+    ],
+    router: |resources| get_routes(resources.order_store.clone()),
+};
 
-pub fn get_routes(pool: PgPool) -> Router {
+pub fn get_routes(store: Arc<dyn OrderStore>) -> Router {
     Router::new()
         .route("/18/reset", post(reset))
         .route("/18/orders", post(orders))
         .route("/18/regions", post(regions))
         .route("/18/regions/total", get(regions_total))
         .route("/18/regions/top_list/:limit", get(regions_top_list))
-        .with_state(DbState { pool })
+        .route("/18/analytics/gifts", get(analytics_gifts))
+        .route("/18/analytics/regions", get(analytics_regions))
+        .route("/18/analytics/pivot", get(analytics_pivot))
+        .route("/18/analytics/percentiles", get(analytics_percentiles))
+        .route("/18/export", get(export))
+        .with_state(DbState { store })
 }
 
-#[derive(serde::Deserialize, Debug)]
-struct Region {
-    id: i64,
-    name: String,
+/// Unlike day 13, orders must name a posted region.
+async fn orders(
+    State(state): State<DbState>,
+    Query(params): Query<InsertParams>,
+    Batch(orders): Batch<Order>,
+) -> Result<Json<InsertReport>, AppError> {
+    info!("18 orders started");
+    let report = state
+        .store
+        .orders(orders, params.mode(), OrderRegions::Known)
+        .await?;
+
+    Ok(report.into())
 }
 
 async fn regions(
     State(state): State<DbState>,
-    Json(regions): Json<Vec<Region>>,
-) -> Result<String, StatusCode> {
+    Query(params): Query<InsertParams>,
+    Batch(regions): Batch<Region>,
+) -> Result<Json<InsertReport>, AppError> {
     info!("18 regions started");
-    let sql = "INSERT INTO regions (id, name) VALUES ($1, $2);";
-    for r in regions {
-        sqlx::query(sql)
-            .bind(r.id)
-            .bind(r.name)
-            .execute(&state.pool)
-            .await
-            .map_err(|err| {
-                dbg!(&err);
-                StatusCode::INTERNAL_SERVER_ERROR
-            })?;
-    }
-
-    Ok("OK".to_string())
-}
+    let report = state.store.regions(regions, params.mode()).await?;
 
-#[derive(Debug, sqlx::FromRow, serde::Serialize)]
-struct RegionsTotalRow {
-    region: String,
-    total: i64,
+    Ok(report.into())
 }
 
 async fn regions_total(
     State(state): State<DbState>,
-) -> Result<Json<Vec<RegionsTotalRow>>, StatusCode> {
+) -> Result<Json<Vec<RegionsTotalRow>>, AppError> {
     info!("18 regions total started");
-    let sql = "SELECT r.name as region, sum(o.quantity) as total
-      FROM orders o
-      INNER JOIN regions r ON o.region_id = r.id
-      GROUP BY r.name
-      ORDER BY r.name;
-  ";
-
-    match sqlx::query_as::<_, RegionsTotalRow>(sql)
-        .fetch_all(&state.pool)
-        .await
-    {
-        Ok(rows) => Ok(rows.into()),
-        Err(err) => {
-            dbg!(err);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
-        }
-    }
+    let rows = state.store.regions_total().await?;
+
+    Ok(rows.into())
 }
 
-#[derive(Debug, sqlx::FromRow, serde::Serialize)]
-struct RegionsTopRow {
-    region: String,
-    top_gifts: sqlx::types::Json<Vec<String>>,
+#[derive(serde::Deserialize, Debug, Default)]
+struct TopListParams {
+    #[serde(default)]
+    ties: Ties,
 }
 
 async fn regions_top_list(
     Path(limit): Path<usize>,
+    Query(params): Query<TopListParams>,
     State(state): State<DbState>,
-) -> Result<Json<Vec<RegionsTopRow>>, StatusCode> {
+) -> Result<Json<Vec<RegionsTopRow>>, AppError> {
     info!("18 regions top list started");
-    let sql = r#"
-        WITH grouped_orders AS (
-            SELECT region_id, gift_name, SUM(quantity) as q
-            FROM orders
-            GROUP BY region_id, gift_name
-            ORDER BY q DESC, gift_name ASC
-        )
-
-        SELECT r.name as region,
-        CASE WHEN COUNT(gift_name) = 0
-            THEN '[]'
-            ELSE json_agg(gift_name)
-            END as top_gifts
-        FROM regions r
-        LEFT JOIN grouped_orders go ON r.id = go.region_id
-        GROUP BY r.name
-        ORDER BY r.name;
-    "#;
-
-    match sqlx::query_as::<_, RegionsTopRow>(sql)
-        .fetch_all(&state.pool)
-        .await
-    {
-        Ok(mut rows) => {
-            rows = rows
-                .into_iter()
-                .map(|mut r| {
-                    r.top_gifts.0 = r.top_gifts.0.into_iter().take(limit).collect();
-                    r
-                })
-                .collect();
-            Ok(rows.into())
+    let rows = state.store.regions_top_list(limit, params.ties).await?;
+
+    Ok(rows.into())
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+#[derive(serde::Deserialize, Debug, Default)]
+struct RankingParams {
+    region: Option<String>,
+    gift: Option<String>,
+    /// `desc` for the top of the ranking, `asc` for the bottom.
+    #[serde(default)]
+    order: SortOrder,
+    limit: Option<usize>,
+}
+
+#[derive(serde::Serialize, Debug)]
+struct Ranked<T> {
+    rank: usize,
+    #[serde(flatten)]
+    row: T,
+}
+
+/// Ranks rows sorted by descending total, ties sharing the same rank (1, 2, 2, 4).
+fn rank<T>(rows: Vec<T>, total: impl Fn(&T) -> i64, params: &RankingParams) -> Vec<Ranked<T>> {
+    let mut ranked: Vec<Ranked<T>> = Vec::with_capacity(rows.len());
+    for (i, row) in rows.into_iter().enumerate() {
+        let rank = match ranked.last() {
+            Some(prev) if total(&prev.row) == total(&row) => prev.rank,
+            _ => i + 1,
+        };
+        ranked.push(Ranked { rank, row });
+    }
+
+    if params.order == SortOrder::Asc {
+        ranked.reverse();
+    }
+    ranked.truncate(params.limit.unwrap_or(usize::MAX));
+    ranked
+}
+
+#[derive(serde::Deserialize, Debug, Default)]
+struct FilterParams {
+    region: Option<String>,
+    gift: Option<String>,
+}
+
+impl From<&RankingParams> for OrderFilter {
+    fn from(params: &RankingParams) -> Self {
+        OrderFilter {
+            region: params.region.clone(),
+            gift: params.gift.clone(),
         }
-        Err(err) => {
-            dbg!(err);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+}
+
+impl From<FilterParams> for OrderFilter {
+    fn from(params: FilterParams) -> Self {
+        OrderFilter {
+            region: params.region,
+            gift: params.gift,
         }
     }
 }
+
+async fn analytics_gifts(
+    Query(params): Query<RankingParams>,
+    State(state): State<DbState>,
+) -> Result<Json<Vec<Ranked<GiftTotalRow>>>, AppError> {
+    info!("18 analytics gifts started");
+    let rows = state.store.gift_totals(&(&params).into()).await?;
+
+    Ok(rank(rows, |r| r.total, &params).into())
+}
+
+async fn analytics_regions(
+    Query(params): Query<RankingParams>,
+    State(state): State<DbState>,
+) -> Result<Json<Vec<Ranked<RegionsTotalRow>>>, AppError> {
+    info!("18 analytics regions started");
+    let rows = state.store.region_totals(&(&params).into()).await?;
+
+    Ok(rank(rows, |r| r.total, &params).into())
+}
+
+#[derive(serde::Serialize, Debug)]
+struct PivotRow {
+    region: String,
+    gifts: BTreeMap<String, i64>,
+    total: i64,
+}
+
+#[derive(serde::Serialize, Debug)]
+struct Pivot {
+    gifts: Vec<String>,
+    regions: Vec<PivotRow>,
+}
+
+fn pivot(rows: Vec<RegionGiftRow>) -> Pivot {
+    let gifts = rows.iter().map(|r| r.gift.clone()).collect::<BTreeSet<_>>();
+
+    let mut regions: Vec<PivotRow> = vec![];
+    for row in rows {
+        if regions.last().is_none_or(|last| last.region != row.region) {
+            regions.push(PivotRow {
+                region: row.region.clone(),
+                gifts: gifts.iter().map(|gift| (gift.clone(), 0)).collect(),
+                total: 0,
+            });
+        }
+        let region = regions.last_mut().expect("pushed above");
+        region.gifts.insert(row.gift, row.total);
+        region.total += row.total;
+    }
+
+    Pivot {
+        gifts: gifts.into_iter().collect(),
+        regions,
+    }
+}
+
+async fn analytics_pivot(
+    Query(params): Query<FilterParams>,
+    State(state): State<DbState>,
+) -> Result<Json<Pivot>, AppError> {
+    info!("18 analytics pivot started");
+    let rows = state.store.region_gift_totals(&params.into()).await?;
+
+    Ok(pivot(rows).into())
+}
+
+#[derive(serde::Deserialize, Debug, Default)]
+struct PercentileParams {
+    region: Option<String>,
+    gift: Option<String>,
+    /// Comma separated percentiles between 0 and 1, e.g. `0.5,0.9`.
+    p: Option<String>,
+}
+
+#[derive(serde::Serialize, Debug)]
+struct PercentileRow {
+    percentile: f64,
+    quantity: Option<f64>,
+}
+
+async fn analytics_percentiles(
+    Query(params): Query<PercentileParams>,
+    State(state): State<DbState>,
+) -> Result<Json<Vec<PercentileRow>>, AppError> {
+    info!("18 analytics percentiles started");
+    let percentiles = parse_percentiles(params.p.as_deref())?;
+
+    let filter = OrderFilter {
+        region: params.region,
+        gift: params.gift,
+    };
+    let quantities = state
+        .store
+        .quantity_percentiles(&filter, &percentiles)
+        .await?;
+
+    Ok(percentiles
+        .into_iter()
+        .enumerate()
+        .map(|(i, percentile)| PercentileRow {
+            percentile,
+            quantity: quantities.as_ref().map(|q| q[i]),
+        })
+        .collect::<Vec<_>>()
+        .into())
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+    Ndjson,
+}
+
+impl ExportFormat {
+    fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Json => "application/json",
+            ExportFormat::Csv => "text/csv",
+            ExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Default)]
+struct ExportParams {
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+fn to_json(row: &ExportRow) -> Result<String, AppError> {
+    serde_json::to_string(row).map_err(|err| AppError::Internal(err.to_string()))
+}
+
+fn to_csv(row: &ExportRow) -> Result<String, AppError> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(vec![]);
+    writer
+        .serialize(row)
+        .map_err(|err| AppError::Internal(err.to_string()))?;
+    let line = writer
+        .into_inner()
+        .map_err(|err| AppError::Internal(err.to_string()))?;
+
+    String::from_utf8(line).map_err(|err| AppError::Internal(err.to_string()))
+}
+
+const CSV_HEADER: &str = "order_id,region_id,region,gift_name,quantity\n";
+
+/// Streams every order with its region without loading them all in memory.
+async fn export(
+    Query(params): Query<ExportParams>,
+    State(state): State<DbState>,
+) -> Result<Response, AppError> {
+    info!("18 export started");
+    let rows = state.store.export();
+
+    let body = match params.format {
+        ExportFormat::Json => stream::once(async { Ok("[".to_string()) })
+            .chain(rows.enumerate().map(|(i, row)| {
+                let json = to_json(&row?)?;
+                Ok(if i == 0 { json } else { format!(",{json}") })
+            }))
+            .chain(stream::once(async { Ok("]".to_string()) }))
+            .boxed(),
+        ExportFormat::Csv => stream::once(async { Ok(CSV_HEADER.to_string()) })
+            .chain(rows.map(|row| to_csv(&row?)))
+            .boxed(),
+        ExportFormat::Ndjson => rows.map(|row| Ok(format!("{}\n", to_json(&row?)?))).boxed(),
+    };
+
+    Ok((
+        [(CONTENT_TYPE, params.format.content_type())],
+        StreamBody::new(body),
+    )
+        .into_response())
+}
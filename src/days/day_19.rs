@@ -1,7 +1,7 @@
 use axum::{
     extract::{
         ws::{Message, WebSocket},
-        Path, State, WebSocketUpgrade,
+        State, WebSocketUpgrade,
     },
     response::Response,
     routing::{get, post},
@@ -9,13 +9,29 @@ use axum::{
 };
 use futures::{sink::SinkExt, stream::StreamExt};
 use log::info;
-use reqwest::StatusCode;
 use std::{
     collections::{HashMap, HashSet},
     sync::{Arc, Mutex},
 };
 use tokio::sync::broadcast;
 
+use super::Day;
+use crate::error::AppError;
+use crate::extract::Path;
+
+pub const DAY: Day = Day {
+    number: 19,
+    title: "Websocket tweeter",
+    requires: &[],
+    routes: &[
+        "GET /19/ws/ping",
+        "POST /19/reset",
+        "GET /19/views",
+        "GET /19/ws/room/:room_number/user/:username",
+    ],
+    router: |_| get_routes(),
+};
+
 pub fn get_routes() -> Router {
     Router::new()
         .route("/19/ws/ping", get(ws_ping))
@@ -34,7 +50,7 @@ pub fn get_routes() -> Router {
 
 async fn ws_ping(ws: WebSocketUpgrade) -> Response {
     info!("19 ws ping started");
-    ws.on_upgrade(|socket| ws_ping_socket(socket))
+    ws.on_upgrade(ws_ping_socket)
 }
 
 async fn ws_ping_socket(mut socket: WebSocket) {
@@ -47,17 +63,14 @@ async fn ws_ping_socket(mut socket: WebSocket) {
             return;
         };
 
-        match msg {
-            axum::extract::ws::Message::Text(text) => {
-                if text == "ping" && game_started {
-                    if let Err(err) = socket.send(Message::Text("pong".to_string())).await {
-                        print!("Error: {:?}", err);
-                    }
-                } else if text == "serve" {
-                    game_started = true;
+        if let Message::Text(text) = msg {
+            if text == "ping" && game_started {
+                if let Err(err) = socket.send(Message::Text("pong".to_string())).await {
+                    print!("Error: {:?}", err);
                 }
+            } else if text == "serve" {
+                game_started = true;
             }
-            _ => {}
         }
     }
 }
@@ -81,9 +94,7 @@ struct UserMsgOut {
     message: String,
 }
 
-async fn tweeter_reset(
-    State(state): State<Arc<TweeterState>>,
-) -> Result<String, (StatusCode, String)> {
+async fn tweeter_reset(State(state): State<Arc<TweeterState>>) -> Result<String, AppError> {
     info!("19 tweeter reset started");
     let mut views = state.views.lock().expect("mutex was poisoned");
     *views = 0;
@@ -91,9 +102,7 @@ async fn tweeter_reset(
     Ok("OK".to_string())
 }
 
-async fn tweeter_views(
-    State(state): State<Arc<TweeterState>>,
-) -> Result<String, (StatusCode, String)> {
+async fn tweeter_views(State(state): State<Arc<TweeterState>>) -> Result<String, AppError> {
     info!("19 tweeter views started");
     let views = state.views.lock().expect("mutex was poisoned");
     info!("views: {views}");
@@ -154,7 +163,9 @@ async fn tweeter_ws(
 
     let mut recv_task = tokio::spawn(async move {
         while let Some(Ok(Message::Text(text))) = receiver.next().await {
-            let user_msg: UserMsgIn = serde_json::from_str(text.as_str()).unwrap();
+            let Ok(user_msg) = serde_json::from_str::<UserMsgIn>(text.as_str()) else {
+                continue;
+            };
             if user_msg.message.len() > 128 {
                 continue;
             }
@@ -193,11 +204,11 @@ fn check_username(state: &TweeterState, name: &str) -> bool {
 fn find_room(state: &TweeterState, room_number: &usize) -> broadcast::Sender<String> {
     let mut rooms = state.rooms.lock().unwrap();
 
-    if let Some(room_tx) = rooms.get(&room_number) {
+    if let Some(room_tx) = rooms.get(room_number) {
         room_tx.clone()
     } else {
         let (tx, _) = broadcast::channel(100_000);
-        rooms.insert(room_number.clone(), tx.clone());
+        rooms.insert(*room_number, tx.clone());
         tx
     }
 }
@@ -1,10 +1,24 @@
 use axum::{body::Bytes, routing::post, Router};
 use git2::{Repository, Tree};
 use log::info;
-use reqwest::StatusCode;
 use tar::Archive;
 use tempfile::tempdir;
 
+use super::Day;
+use crate::error::AppError;
+
+pub const DAY: Day = Day {
+    number: 20,
+    title: "Git archives",
+    requires: &[],
+    routes: &[
+        "POST /20/archive_files",
+        "POST /20/archive_files_size",
+        "POST /20/cookie",
+    ],
+    router: |_| get_routes(),
+};
+
 pub fn get_routes() -> Router {
     Router::new()
         .route("/20/archive_files", post(archive_files))
@@ -12,80 +26,88 @@ pub fn get_routes() -> Router {
         .route("/20/cookie", post(cookie))
 }
 
-async fn archive_files(body: Bytes) -> Result<String, (StatusCode, String)> {
+fn invalid_archive(err: std::io::Error) -> AppError {
+    AppError::BadRequest(format!("invalid tar archive: {err}"))
+}
+
+async fn archive_files(body: Bytes) -> Result<String, AppError> {
     info!("20 archive files started");
     let mut a = Archive::new(body.as_ref());
+    let mut count = 0;
+    for file in a.entries().map_err(invalid_archive)? {
+        file.map_err(invalid_archive)?;
+        count += 1;
+    }
 
-    Ok(a.entries().unwrap().count().to_string())
+    Ok(count.to_string())
 }
 
-async fn archive_files_size(body: Bytes) -> Result<String, (StatusCode, String)> {
+async fn archive_files_size(body: Bytes) -> Result<String, AppError> {
     info!("20 archive files size");
     let mut a = Archive::new(body.as_ref());
+    let mut size = 0;
+    for file in a.entries().map_err(invalid_archive)? {
+        size += file
+            .map_err(invalid_archive)?
+            .header()
+            .size()
+            .map_err(invalid_archive)?;
+    }
 
-    Ok(a.entries()
-        .unwrap()
-        .map(|file| file.unwrap().header().size().unwrap())
-        .sum::<u64>()
-        .to_string())
+    Ok(size.to_string())
 }
 
-async fn cookie(body: Bytes) -> Result<String, (StatusCode, String)> {
+async fn cookie(body: Bytes) -> Result<String, AppError> {
     info!("20 cookie started");
     let mut a = Archive::new(body.as_ref());
-    let temp_dir = tempdir().unwrap();
-    a.unpack(temp_dir.path()).unwrap();
-
-    let repo = match Repository::init(temp_dir.path()) {
-        Ok(repo) => repo,
-        Err(e) => {
-            return Err((
-                StatusCode::BAD_REQUEST,
-                format!("failed to init Git: {}", e),
-            ))
-        }
-    };
+    let temp_dir = tempdir().map_err(|err| AppError::Internal(err.to_string()))?;
+    a.unpack(temp_dir.path()).map_err(invalid_archive)?;
 
-    let mut n = 0;
+    let repo = Repository::init(temp_dir.path())
+        .map_err(|err| AppError::BadRequest(format!("failed to init Git: {}", err)))?;
 
-    for _ in 1..1000 {
+    for n in 0..999 {
         let rev = match n {
             0 => "christmas".to_string(),
             n => format!("christmas@{{{}}}", n),
         };
-        let head = repo.revparse_ext(rev.as_str()).unwrap().0;
-        let commit = head.as_commit().unwrap();
+        let Ok((head, _)) = repo.revparse_ext(rev.as_str()) else {
+            break;
+        };
+        let commit = head
+            .as_commit()
+            .ok_or_else(|| AppError::Unprocessable(format!("{rev} is not a commit")))?;
         let id = commit.id();
-        let author = commit.author().name().unwrap().to_string();
+        let author = commit.author().name().unwrap_or_default().to_string();
 
-        let tree = commit.tree().unwrap();
+        let tree = commit
+            .tree()
+            .map_err(|err| AppError::Unprocessable(err.to_string()))?;
         if christmas_walk(&tree, &repo).is_some() {
             return Ok(format!("{author} {id}"));
         }
-        n += 1;
     }
 
-    Err((StatusCode::BAD_REQUEST, "no cookie".to_string()))
+    Err(AppError::BadRequest("no cookie".to_string()))
 }
 
 fn christmas_walk(tree: &Tree, repo: &Repository) -> Option<()> {
     for entry in tree.iter() {
-        let x = entry.to_object(&repo).unwrap();
+        let x = entry.to_object(repo).ok()?;
         if let Some(tt) = x.as_tree() {
-            if christmas_walk(&tt, repo).is_some() {
+            if christmas_walk(tt, repo).is_some() {
                 return Some(());
             }
             continue;
         }
 
-        let name = entry.name().unwrap();
-        if name != "santa.txt" {
+        if entry.name() != Some("santa.txt") {
             continue;
         }
-        let o = entry.to_object(&repo).unwrap().peel_to_blob().unwrap();
-        let content = String::from_utf8(o.content().to_vec()).unwrap();
+        let o = x.peel_to_blob().ok()?;
+        let content = String::from_utf8_lossy(o.content());
 
-        if content.find("COOKIE").is_some() {
+        if content.contains("COOKIE") {
             return Some(());
         }
     }
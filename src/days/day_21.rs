@@ -1,10 +1,20 @@
-use axum::{extract::Path, routing::get, Router};
+use axum::{routing::get, Router};
 use dms_coordinates::DMS;
 use log::info;
-use reqwest::StatusCode;
 use reverse_geocoder::ReverseGeocoder;
 
+use super::Day;
 use crate::countries::countries;
+use crate::error::AppError;
+use crate::extract::Path;
+
+pub const DAY: Day = Day {
+    number: 21,
+    title: "S2 coordinates",
+    requires: &[],
+    routes: &["GET /21/coords/:s2", "GET /21/country/:s2"],
+    router: |_| get_routes(),
+};
 
 pub fn get_routes() -> Router {
     Router::new()
@@ -12,30 +22,43 @@ pub fn get_routes() -> Router {
         .route("/21/country/:s2", get(s2_country))
 }
 
-async fn s2_coords(Path(s2_string): Path<String>) -> Result<String, (StatusCode, String)> {
+async fn s2_coords(Path(s2_string): Path<String>) -> Result<String, AppError> {
     info!("21 s2 coords started");
-    let s2_coords = u64::from_str_radix(s2_string.as_str(), 2).unwrap();
-    let cell = s2::cellid::CellID(s2_coords);
+    let cell = parse_cell(&s2_string)?;
     let ll = s2::latlng::LatLng::from(cell);
     let mut lat = DMS::from_decimal_degrees(ll.lat.deg(), true);
     let mut long = DMS::from_decimal_degrees(ll.lng.deg(), false);
     lat.seconds = (lat.seconds * 1000.).round() / 1000.;
     long.seconds = (long.seconds * 1000.).round() / 1000.;
 
-    Ok(format!("{} {}", lat.to_string(), long.to_string()))
+    Ok(format!("{} {}", lat, long))
 }
 
-async fn s2_country(Path(s2_string): Path<String>) -> Result<String, (StatusCode, String)> {
+async fn s2_country(Path(s2_string): Path<String>) -> Result<String, AppError> {
     info!("21 s2 country started");
-    let s2_coords = u64::from_str_radix(s2_string.as_str(), 2).unwrap();
-    let cell = s2::cellid::CellID(s2_coords);
+    let cell = parse_cell(&s2_string)?;
     let ll = s2::latlng::LatLng::from(cell);
 
     let geocoder = ReverseGeocoder::new();
     let search_result = geocoder.search((ll.lat.deg(), ll.lng.deg()));
     let cc = search_result.record.cc.clone();
     let countries = countries();
-    let country = countries.get(&cc).unwrap();
+    let country = countries
+        .get(&cc)
+        .ok_or_else(|| AppError::NotFound(format!("unknown country code {cc}")))?;
 
     Ok(country.clone())
 }
+
+fn parse_cell(s2_string: &str) -> Result<s2::cellid::CellID, AppError> {
+    let s2_coords = u64::from_str_radix(s2_string, 2)
+        .map_err(|err| AppError::BadRequest(format!("invalid s2 cell id '{s2_string}': {err}")))?;
+    let cell = s2::cellid::CellID(s2_coords);
+    if !cell.is_valid() {
+        return Err(AppError::Unprocessable(format!(
+            "{s2_string} is not a valid s2 cell"
+        )));
+    }
+
+    Ok(cell)
+}
@@ -2,7 +2,17 @@ use std::collections::HashSet;
 
 use axum::{routing::post, Router};
 use log::info;
-use reqwest::StatusCode;
+
+use super::Day;
+use crate::error::AppError;
+
+pub const DAY: Day = Day {
+    number: 22,
+    title: "Rocket portals",
+    requires: &[],
+    routes: &["POST /22/integers", "POST /22/rocket"],
+    router: |_| get_routes(),
+};
 
 pub fn get_routes() -> Router {
     Router::new()
@@ -10,18 +20,23 @@ pub fn get_routes() -> Router {
         .route("/22/rocket", post(rocket))
 }
 
-async fn integers(body: String) -> Result<String, (StatusCode, String)> {
+/// Gifts `/22/integers` answers with at most, 4 bytes each.
+const MAX_GIFTS: usize = 1 << 20;
+
+async fn integers(body: String) -> Result<String, AppError> {
     info!("22 integers started");
 
     let nums = body
         .trim()
-        .split("\n")
-        .map(|s| s.parse::<usize>().unwrap())
-        .collect::<Vec<usize>>();
-
-    let mut num = nums[0];
-    for i in 1..nums.len() {
-        num ^= nums[i];
+        .split('\n')
+        .map(parse_number::<usize>)
+        .collect::<Result<Vec<usize>, AppError>>()?;
+
+    let num = nums.iter().fold(0, |acc, n| acc ^ n);
+    if num > MAX_GIFTS {
+        return Err(AppError::Unprocessable(format!(
+            "cannot wrap {num} gifts, at most {MAX_GIFTS} are allowed"
+        )));
     }
 
     Ok("🎁".repeat(num))
@@ -34,10 +49,10 @@ struct Galaxy {
     portals: Vec<(i32, i32)>,
 }
 
-async fn rocket(body: String) -> Result<String, (StatusCode, String)> {
+async fn rocket(body: String) -> Result<String, AppError> {
     info!("22 rocket started");
 
-    let galaxy = parse_input(body);
+    let galaxy = parse_input(body)?;
     let mut path = HashSet::new();
     let mut min_path = HashSet::new();
     backtrace(0, &galaxy, &mut path, &mut min_path);
@@ -60,29 +75,65 @@ async fn rocket(body: String) -> Result<String, (StatusCode, String)> {
     }
 }
 
-fn parse_input(body: String) -> Galaxy {
-    let strs = body.trim().split("\n").collect::<Vec<&str>>();
-    let n = strs[0].parse::<usize>().unwrap();
+fn parse_number<T: std::str::FromStr>(s: &str) -> Result<T, AppError>
+where
+    T::Err: std::fmt::Display,
+{
+    s.trim()
+        .parse::<T>()
+        .map_err(|err| AppError::BadRequest(format!("invalid number '{s}': {err}")))
+}
+
+fn parse_line(strs: &[&str], i: usize) -> Result<Vec<i32>, AppError> {
+    strs.get(i)
+        .ok_or_else(|| AppError::BadRequest(format!("line {} is missing", i + 1)))?
+        .split(' ')
+        .map(parse_number::<i32>)
+        .collect()
+}
+
+fn parse_count(strs: &[&str], i: usize) -> Result<usize, AppError> {
+    parse_number(
+        strs.get(i)
+            .ok_or_else(|| AppError::BadRequest(format!("line {} is missing", i + 1)))?,
+    )
+}
+
+fn parse_input(body: String) -> Result<Galaxy, AppError> {
+    let strs = body.trim().split('\n').collect::<Vec<&str>>();
+    let n = parse_count(&strs, 0)?;
     let mut stars = vec![];
     for i in 0..n {
-        let coords = strs[i + 1]
-            .split(" ")
-            .map(|s| s.parse::<i32>().unwrap())
-            .collect::<Vec<i32>>();
-        stars.push([coords[0], coords[1], coords[2]]);
+        let coords = parse_line(&strs, i + 1)?;
+        let [x, y, z] = coords[..] else {
+            return Err(AppError::BadRequest(format!(
+                "star {i} must have 3 coordinates"
+            )));
+        };
+        stars.push([x, y, z]);
     }
 
-    let k = strs[n + 1].parse::<usize>().unwrap();
+    let k = parse_count(&strs, n + 1)?;
     let mut portals = vec![];
     for i in 0..k {
-        let path = strs[n + 2 + i]
-            .split(" ")
-            .map(|s| s.parse::<i32>().unwrap())
-            .collect::<Vec<i32>>();
-        portals.push((path[0], path[1]));
+        let path = parse_line(&strs, n + 2 + i)?;
+        let [from, to] = path[..] else {
+            return Err(AppError::BadRequest(format!(
+                "portal {i} must connect 2 stars"
+            )));
+        };
+        if [from, to]
+            .iter()
+            .any(|&star| star < 0 || star as usize >= n)
+        {
+            return Err(AppError::Unprocessable(format!(
+                "portal {i} leads to an unknown star"
+            )));
+        }
+        portals.push((from, to));
     }
 
-    Galaxy { n, stars, portals }
+    Ok(Galaxy { n, stars, portals })
 }
 
 fn backtrace(
@@ -98,14 +149,14 @@ fn backtrace(
             }
 
             if portal.1 + 1 == galaxy.n as i32 {
-                if min_path.len() == 0 || path.len() + 1 < min_path.len() {
+                if min_path.is_empty() || path.len() + 1 < min_path.len() {
                     *min_path = path.clone();
-                    min_path.insert(portal.clone());
+                    min_path.insert(portal);
                 }
 
                 return;
             } else {
-                path.insert(portal.clone());
+                path.insert(portal);
                 backtrace(portal.1 as usize, galaxy, path, min_path);
                 path.remove(&portal);
             }
@@ -1,3 +1,18 @@
+use axum::{extract::State, routing::get, Json, Router};
+use log::{info, warn};
+use sqlx::PgPool;
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+#[cfg(feature = "clock-admin")]
+use crate::clock::FakeClock;
+#[cfg(not(feature = "clock-admin"))]
+use crate::clock::SystemClock;
+use crate::{clock::Clock, error::ConfigError};
+use day_07::RecipeCookies;
+use order_store::OrderStore;
+use pokemon_source::PokemonSource;
+use time_store::TimeStore;
+
 pub mod day_00;
 pub mod day_01;
 pub mod day_04;
@@ -15,3 +30,201 @@ pub mod day_19;
 pub mod day_20;
 pub mod day_21;
 pub mod day_22;
+pub mod order_store;
+pub mod pokemon_source;
+pub mod quantity;
+pub mod recipe_planner;
+pub mod time_store;
+pub mod todos;
+
+/// Something a day needs from the outside world to be mounted.
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Resource {
+    Database,
+}
+
+/// Everything the days can be built from.
+#[derive(Clone)]
+pub struct Resources {
+    pub pool: Option<PgPool>,
+    pub order_store: Arc<dyn OrderStore>,
+    pub time_store: Arc<dyn TimeStore>,
+    pub clock: Arc<dyn Clock>,
+    pub recipe_cookies: Arc<RecipeCookies>,
+    pub pokemon_source: Arc<dyn PokemonSource>,
+    /// Same clock as `clock`, driven by the `/admin/clock` endpoints.
+    #[cfg(feature = "clock-admin")]
+    pub fake_clock: Arc<FakeClock>,
+}
+
+/// How often expired day 12 keys are removed from the time store.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+impl Resources {
+    /// Wraps the optional pool, picking the stores, the recipe cookie keys and the
+    /// Pokémon source from the environment. Must be called from a tokio runtime,
+    /// which runs the time store's sweeper. The clock is the system one, or a fake
+    /// one with the `clock-admin` feature.
+    pub fn from_env(pool: Option<PgPool>) -> Result<Self, ConfigError> {
+        let order_store = order_store::from_env(pool.clone())?;
+        let time_store = time_store::from_env(pool.clone())?;
+        let recipe_cookies = Arc::new(RecipeCookies::from_env()?);
+
+        #[cfg(feature = "clock-admin")]
+        let fake_clock = Arc::new(FakeClock::default());
+        #[cfg(feature = "clock-admin")]
+        let clock: Arc<dyn Clock> = fake_clock.clone();
+        #[cfg(not(feature = "clock-admin"))]
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+
+        let pokemon_source = pokemon_source::from_env(clock.clone())?;
+        time_store::spawn_sweeper(time_store.clone(), clock.clone(), SWEEP_INTERVAL);
+
+        Ok(Resources {
+            pool,
+            order_store,
+            time_store,
+            clock,
+            recipe_cookies,
+            pokemon_source,
+            #[cfg(feature = "clock-admin")]
+            fake_clock,
+        })
+    }
+
+    fn has(&self, resource: Resource) -> bool {
+        match resource {
+            Resource::Database => self.pool.is_some(),
+        }
+    }
+}
+
+/// A registered day: its metadata and how to build its router.
+pub struct Day {
+    pub number: i8,
+    pub title: &'static str,
+    pub requires: &'static [Resource],
+    pub routes: &'static [&'static str],
+    pub router: fn(&Resources) -> Router,
+}
+
+pub fn registry() -> Vec<Day> {
+    vec![
+        day_00::DAY,
+        todos::DAY,
+        day_01::DAY,
+        day_04::DAY,
+        day_05::DAY,
+        day_06::DAY,
+        day_07::DAY,
+        day_08::DAY,
+        day_11::DAY,
+        day_12::DAY,
+        day_13::DAY,
+        day_14::DAY,
+        day_15::DAY,
+        day_18::DAY,
+        day_19::DAY,
+        day_20::DAY,
+        day_21::DAY,
+        day_22::DAY,
+    ]
+}
+
+/// Which days get mounted. Read from `CCH_ENABLED_DAYS` and `CCH_DISABLED_DAYS`,
+/// both comma separated day numbers (e.g. `CCH_DISABLED_DAYS=8,21`).
+#[derive(Debug, Default)]
+pub struct DaysConfig {
+    pub enabled: Option<HashSet<i8>>,
+    pub disabled: HashSet<i8>,
+}
+
+impl DaysConfig {
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let enabled = match std::env::var("CCH_ENABLED_DAYS") {
+            Ok(days) => Some(parse_days(&days)?),
+            Err(_) => None,
+        };
+        let disabled = match std::env::var("CCH_DISABLED_DAYS") {
+            Ok(days) => parse_days(&days)?,
+            Err(_) => HashSet::new(),
+        };
+
+        Ok(DaysConfig { enabled, disabled })
+    }
+
+    fn is_enabled(&self, number: i8) -> bool {
+        let enabled = match &self.enabled {
+            Some(enabled) => enabled.contains(&number),
+            None => true,
+        };
+
+        enabled && !self.disabled.contains(&number)
+    }
+}
+
+fn parse_days(days: &str) -> Result<HashSet<i8>, ConfigError> {
+    days.split(',')
+        .map(str::trim)
+        .filter(|day| !day.is_empty())
+        .map(|day| {
+            day.parse::<i8>()
+                .map_err(|err| ConfigError(format!("invalid day '{day}': {err}")))
+        })
+        .collect()
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+struct DayInfo {
+    day: i8,
+    title: &'static str,
+    requires: &'static [Resource],
+    routes: &'static [&'static str],
+    enabled: bool,
+}
+
+/// Builds the router of every enabled day whose resources are available,
+/// plus `GET /days` listing all registered days.
+pub fn get_routes(config: &DaysConfig, resources: &Resources) -> Router {
+    let mut router = Router::new();
+    let mut infos = vec![];
+
+    for day in registry() {
+        let missing = day
+            .requires
+            .iter()
+            .filter(|resource| !resources.has(**resource))
+            .collect::<Vec<_>>();
+
+        let enabled = config.is_enabled(day.number) && missing.is_empty();
+        if enabled {
+            info!("day {} enabled", day.number);
+            router = router.merge((day.router)(resources));
+        } else if !missing.is_empty() {
+            warn!("day {} disabled, missing {:?}", day.number, missing);
+        } else {
+            info!("day {} disabled", day.number);
+        }
+
+        infos.push(DayInfo {
+            day: day.number,
+            title: day.title,
+            requires: day.requires,
+            routes: day.routes,
+            enabled,
+        });
+    }
+
+    router.merge(
+        Router::new()
+            .route("/days", get(days))
+            .with_state(Arc::new(infos)),
+    )
+}
+
+async fn days(State(infos): State<Arc<Vec<DayInfo>>>) -> Json<Vec<DayInfo>> {
+    info!("days started");
+
+    Json(infos.as_ref().clone())
+}
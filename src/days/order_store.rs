@@ -0,0 +1,944 @@
+use async_trait::async_trait;
+use futures::{stream::BoxStream, StreamExt};
+use sqlx::PgPool;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+use crate::error::{AppError, ConfigError, ItemError};
+use crate::stats::percentile_cont;
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct Order {
+    pub id: i64,
+    pub region_id: i64,
+    pub gift_name: String,
+    pub quantity: i64,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct Region {
+    pub id: i64,
+    pub name: String,
+}
+
+/// Whether a batch may overwrite rows that already exist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertMode {
+    Insert,
+    Upsert,
+}
+
+/// Which regions the orders of a batch may name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderRegions {
+    /// Any region, posted or not, as day 13 never posts any.
+    Any,
+    /// Only posted regions, so that no order is left out of the analytics.
+    Known,
+}
+
+#[derive(serde::Serialize, Debug, Default)]
+pub struct InsertReport {
+    pub inserted: usize,
+    pub updated: usize,
+}
+
+#[derive(Debug, sqlx::FromRow, serde::Serialize)]
+pub struct RegionsTotalRow {
+    pub region: String,
+    pub total: i64,
+}
+
+#[derive(Debug, sqlx::FromRow, serde::Serialize)]
+pub struct GiftTotalRow {
+    pub gift: String,
+    pub total: i64,
+}
+
+#[derive(Debug, sqlx::FromRow, serde::Serialize)]
+pub struct RegionGiftRow {
+    pub region: String,
+    pub gift: String,
+    pub total: i64,
+}
+
+/// How gifts ordered in the same quantity are ranked in top lists.
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Ties {
+    /// One rank per gift, ties broken by gift name: never more than `limit` gifts.
+    #[default]
+    Strict,
+    /// Tied gifts share a rank: the list can be longer than `limit`.
+    Dense,
+}
+
+/// Restricts analytics to one region and/or one gift, by name.
+#[derive(Debug, Default, Clone)]
+pub struct OrderFilter {
+    pub region: Option<String>,
+    pub gift: Option<String>,
+}
+
+#[derive(Debug, sqlx::FromRow, serde::Serialize)]
+pub struct RegionsTopRow {
+    pub region: String,
+    pub top_gifts: sqlx::types::Json<Vec<String>>,
+}
+
+/// An order joined with its region, as exported by `/18/export`.
+#[derive(Debug, sqlx::FromRow, serde::Serialize)]
+pub struct ExportRow {
+    pub order_id: i64,
+    pub region_id: i64,
+    pub region: String,
+    pub gift_name: String,
+    pub quantity: i64,
+}
+
+/// Storage behind days 13 and 18.
+#[async_trait]
+pub trait OrderStore: Send + Sync {
+    /// Round-trips a number through the store, used as a health check.
+    async fn echo(&self, n: i64) -> Result<i64, AppError>;
+    async fn reset(&self) -> Result<(), AppError>;
+    /// Inserts the whole batch or nothing, rejecting it with every invalid row.
+    async fn orders(
+        &self,
+        orders: Vec<Order>,
+        mode: InsertMode,
+        regions: OrderRegions,
+    ) -> Result<InsertReport, AppError>;
+    async fn orders_total(&self) -> Result<i64, AppError>;
+    async fn orders_popular(&self) -> Result<Option<String>, AppError>;
+    /// Inserts the whole batch or nothing, rejecting it with every invalid row.
+    async fn regions(
+        &self,
+        regions: Vec<Region>,
+        mode: InsertMode,
+    ) -> Result<InsertReport, AppError>;
+    async fn regions_total(&self) -> Result<Vec<RegionsTotalRow>, AppError>;
+    /// Most ordered gifts of every region, at most `limit` ranks each.
+    async fn regions_top_list(
+        &self,
+        limit: usize,
+        ties: Ties,
+    ) -> Result<Vec<RegionsTopRow>, AppError>;
+    /// Quantity per gift, most ordered first.
+    async fn gift_totals(&self, filter: &OrderFilter) -> Result<Vec<GiftTotalRow>, AppError>;
+    /// Quantity per region, most ordered first.
+    async fn region_totals(&self, filter: &OrderFilter) -> Result<Vec<RegionsTotalRow>, AppError>;
+    /// Quantity per region and gift, sorted by region then gift.
+    async fn region_gift_totals(
+        &self,
+        filter: &OrderFilter,
+    ) -> Result<Vec<RegionGiftRow>, AppError>;
+    /// Continuous percentiles of the order quantities, `None` without orders.
+    async fn quantity_percentiles(
+        &self,
+        filter: &OrderFilter,
+        percentiles: &[f64],
+    ) -> Result<Option<Vec<f64>>, AppError>;
+    /// Every order with its region, by order id, streamed as it is read.
+    fn export(&self) -> BoxStream<'static, Result<ExportRow, AppError>>;
+}
+
+/// Picks the store from `ORDER_STORE` (`postgres` or `memory`). Defaults to
+/// Postgres when a pool is available, to memory otherwise.
+pub fn from_env(pool: Option<PgPool>) -> Result<Arc<dyn OrderStore>, ConfigError> {
+    let kind = std::env::var("ORDER_STORE").ok();
+    match (kind.as_deref(), pool) {
+        (None | Some("postgres"), Some(pool)) => Ok(Arc::new(PgOrderStore { pool })),
+        (Some("postgres"), None) => Err(ConfigError(
+            "ORDER_STORE=postgres needs a database".to_string(),
+        )),
+        (None | Some("memory"), _) => Ok(Arc::new(MemoryOrderStore::default())),
+        (Some(kind), _) => Err(ConfigError(format!("unknown order store {kind}"))),
+    }
+}
+
+/// Characters in gift and region names, stored as `VARCHAR(50)`.
+const MAX_NAME_LEN: usize = 50;
+
+/// Reports `name` in `errors` when it does not fit its column.
+fn validate_name(index: usize, field: &str, name: &str, errors: &mut Vec<ItemError>) {
+    let len = name.chars().count();
+    if len > MAX_NAME_LEN {
+        errors.push(ItemError {
+            index,
+            detail: format!("{field} is {len} characters long, at most {MAX_NAME_LEN} are allowed"),
+        });
+    }
+}
+
+/// Checks the ids of a batch against themselves and the `existing` ones.
+fn validate_ids(
+    ids: impl Iterator<Item = i64>,
+    existing: &HashSet<i64>,
+    mode: InsertMode,
+    errors: &mut Vec<ItemError>,
+) {
+    let mut seen = HashSet::new();
+    for (index, id) in ids.enumerate() {
+        let detail = if i32::try_from(id).is_err() {
+            format!("id {id} is out of range")
+        } else if !seen.insert(id) {
+            format!("id {id} appears more than once")
+        } else if mode == InsertMode::Insert && existing.contains(&id) {
+            format!("id {id} already exists")
+        } else {
+            continue;
+        };
+        errors.push(ItemError { index, detail });
+    }
+}
+
+/// Checks a batch of orders. With `known_regions`, every order must name one
+/// of them.
+fn validate_orders(
+    orders: &[Order],
+    existing: &HashSet<i64>,
+    known_regions: Option<&HashSet<i64>>,
+    mode: InsertMode,
+) -> Result<InsertReport, AppError> {
+    let mut errors = vec![];
+    validate_ids(orders.iter().map(|o| o.id), existing, mode, &mut errors);
+    for (index, order) in orders.iter().enumerate() {
+        for (field, value) in [("region_id", order.region_id), ("quantity", order.quantity)] {
+            if i32::try_from(value).is_err() {
+                errors.push(ItemError {
+                    index,
+                    detail: format!("{field} {value} is out of range"),
+                });
+            }
+        }
+        validate_name(index, "gift_name", &order.gift_name, &mut errors);
+        if known_regions.is_some_and(|known| !known.contains(&order.region_id)) {
+            errors.push(ItemError {
+                index,
+                detail: format!("unknown region {}", order.region_id),
+            });
+        }
+    }
+
+    report(orders.iter().map(|o| o.id), existing, errors)
+}
+
+fn validate_regions(
+    regions: &[Region],
+    existing: &HashSet<i64>,
+    mode: InsertMode,
+) -> Result<InsertReport, AppError> {
+    let mut errors = vec![];
+    validate_ids(regions.iter().map(|r| r.id), existing, mode, &mut errors);
+    for (index, region) in regions.iter().enumerate() {
+        validate_name(index, "name", &region.name, &mut errors);
+    }
+
+    report(regions.iter().map(|r| r.id), existing, errors)
+}
+
+fn report(
+    ids: impl Iterator<Item = i64>,
+    existing: &HashSet<i64>,
+    mut errors: Vec<ItemError>,
+) -> Result<InsertReport, AppError> {
+    if !errors.is_empty() {
+        errors.sort_by_key(|err| err.index);
+        return Err(AppError::InvalidItems(errors));
+    }
+
+    let mut report = InsertReport::default();
+    for id in ids {
+        if existing.contains(&id) {
+            report.updated += 1;
+        } else {
+            report.inserted += 1;
+        }
+    }
+
+    Ok(report)
+}
+
+/// `ON CONFLICT` clause of a batch insert: rows that already exist are left
+/// alone when inserting, so that [`check_inserted`] can report them.
+fn on_conflict(mode: InsertMode, update: &str) -> String {
+    match mode {
+        InsertMode::Insert => "ON CONFLICT (id) DO NOTHING".to_string(),
+        InsertMode::Upsert => format!("ON CONFLICT (id) DO UPDATE SET {update}"),
+    }
+}
+
+/// Fails with the ids of the batch that were not `inserted`, which were created
+/// by someone else since they were checked.
+fn check_inserted(ids: &[i64], inserted: &[i32], mode: InsertMode) -> Result<(), AppError> {
+    if mode == InsertMode::Upsert || inserted.len() == ids.len() {
+        return Ok(());
+    }
+    let inserted = inserted
+        .iter()
+        .copied()
+        .map(i64::from)
+        .collect::<HashSet<_>>();
+    let errors = ids
+        .iter()
+        .enumerate()
+        .filter(|(_, id)| !inserted.contains(id))
+        .map(|(index, id)| ItemError {
+            index,
+            detail: format!("id {id} already exists"),
+        })
+        .collect();
+
+    Err(AppError::InvalidItems(errors))
+}
+
+/// `WHERE` clause applying an [`OrderFilter`] bound as `$1` (region) and `$2` (gift).
+const PG_FILTER: &str = "WHERE ($1::TEXT IS NULL OR r.name = $1)
+    AND ($2::TEXT IS NULL OR o.gift_name = $2)";
+
+pub struct PgOrderStore {
+    pub pool: PgPool,
+}
+
+#[async_trait]
+impl OrderStore for PgOrderStore {
+    async fn echo(&self, n: i64) -> Result<i64, AppError> {
+        let row: (i64,) = sqlx::query_as("SELECT $1")
+            .bind(n)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(row.0)
+    }
+
+    async fn reset(&self) -> Result<(), AppError> {
+        sqlx::query("TRUNCATE orders, regions;")
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn orders(
+        &self,
+        orders: Vec<Order>,
+        mode: InsertMode,
+        regions: OrderRegions,
+    ) -> Result<InsertReport, AppError> {
+        let ids = orders.iter().map(|o| o.id).collect::<Vec<_>>();
+        let region_ids = orders.iter().map(|o| o.region_id).collect::<Vec<_>>();
+
+        let mut tx = self.pool.begin().await?;
+        let existing = sqlx::query_scalar::<_, i32>("SELECT id FROM orders WHERE id = ANY($1)")
+            .bind(&ids)
+            .fetch_all(&mut *tx)
+            .await?
+            .into_iter()
+            .map(i64::from)
+            .collect();
+        let known_regions = match regions {
+            OrderRegions::Any => None,
+            OrderRegions::Known => Some(
+                sqlx::query_scalar::<_, i32>("SELECT id FROM regions WHERE id = ANY($1)")
+                    .bind(&region_ids)
+                    .fetch_all(&mut *tx)
+                    .await?
+                    .into_iter()
+                    .map(i64::from)
+                    .collect(),
+            ),
+        };
+        let report = validate_orders(&orders, &existing, known_regions.as_ref(), mode)?;
+
+        let sql = format!(
+            "INSERT INTO orders (id, region_id, gift_name, quantity)
+            SELECT * FROM UNNEST($1::INT8[], $2::INT8[], $3::TEXT[], $4::INT8[])
+            {}
+            RETURNING id",
+            on_conflict(
+                mode,
+                "region_id = EXCLUDED.region_id,
+                gift_name = EXCLUDED.gift_name,
+                quantity = EXCLUDED.quantity"
+            )
+        );
+        let inserted = sqlx::query_scalar::<_, i32>(&sql)
+            .bind(&ids)
+            .bind(region_ids)
+            .bind(
+                orders
+                    .iter()
+                    .map(|o| o.gift_name.clone())
+                    .collect::<Vec<_>>(),
+            )
+            .bind(orders.iter().map(|o| o.quantity).collect::<Vec<_>>())
+            .fetch_all(&mut *tx)
+            .await?;
+        check_inserted(&ids, &inserted, mode)?;
+        tx.commit().await?;
+
+        Ok(report)
+    }
+
+    async fn orders_total(&self) -> Result<i64, AppError> {
+        let sql = "SELECT COALESCE(SUM(quantity), 0) FROM orders";
+        let row: (i64,) = sqlx::query_as(sql).fetch_one(&self.pool).await?;
+
+        Ok(row.0)
+    }
+
+    async fn orders_popular(&self) -> Result<Option<String>, AppError> {
+        let sql =
+            "SELECT gift_name FROM orders GROUP BY gift_name ORDER BY SUM(quantity) DESC LIMIT 1";
+        let row = sqlx::query_as::<_, (String,)>(sql)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(|row| row.0))
+    }
+
+    async fn regions(
+        &self,
+        regions: Vec<Region>,
+        mode: InsertMode,
+    ) -> Result<InsertReport, AppError> {
+        let ids = regions.iter().map(|r| r.id).collect::<Vec<_>>();
+
+        let mut tx = self.pool.begin().await?;
+        let existing = sqlx::query_scalar::<_, i32>("SELECT id FROM regions WHERE id = ANY($1)")
+            .bind(&ids)
+            .fetch_all(&mut *tx)
+            .await?
+            .into_iter()
+            .map(i64::from)
+            .collect();
+        let report = validate_regions(&regions, &existing, mode)?;
+
+        let sql = format!(
+            "INSERT INTO regions (id, name)
+            SELECT * FROM UNNEST($1::INT8[], $2::TEXT[])
+            {}
+            RETURNING id",
+            on_conflict(mode, "name = EXCLUDED.name")
+        );
+        let inserted = sqlx::query_scalar::<_, i32>(&sql)
+            .bind(&ids)
+            .bind(regions.into_iter().map(|r| r.name).collect::<Vec<_>>())
+            .fetch_all(&mut *tx)
+            .await?;
+        check_inserted(&ids, &inserted, mode)?;
+        tx.commit().await?;
+
+        Ok(report)
+    }
+
+    async fn regions_total(&self) -> Result<Vec<RegionsTotalRow>, AppError> {
+        let sql = "SELECT r.name as region, sum(o.quantity) as total
+          FROM orders o
+          INNER JOIN regions r ON o.region_id = r.id
+          GROUP BY r.name
+          ORDER BY r.name;
+        ";
+
+        Ok(sqlx::query_as::<_, RegionsTotalRow>(sql)
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn regions_top_list(
+        &self,
+        limit: usize,
+        ties: Ties,
+    ) -> Result<Vec<RegionsTopRow>, AppError> {
+        let sql = r#"
+            WITH ranked_gifts AS (
+                SELECT region_id, gift_name, SUM(quantity) AS q,
+                    ROW_NUMBER() OVER (
+                        PARTITION BY region_id ORDER BY SUM(quantity) DESC, gift_name ASC
+                    ) AS strict_rank,
+                    DENSE_RANK() OVER (
+                        PARTITION BY region_id ORDER BY SUM(quantity) DESC
+                    ) AS dense_rank
+                FROM orders
+                GROUP BY region_id, gift_name
+            )
+
+            SELECT r.name AS region,
+            COALESCE(
+                json_agg(rg.gift_name ORDER BY rg.q DESC, rg.gift_name ASC)
+                    FILTER (WHERE rg.gift_name IS NOT NULL),
+                '[]'
+            ) AS top_gifts
+            FROM regions r
+            LEFT JOIN ranked_gifts rg ON r.id = rg.region_id
+                AND CASE WHEN $2 THEN rg.dense_rank ELSE rg.strict_rank END <= $1
+            GROUP BY r.name
+            ORDER BY r.name;
+        "#;
+
+        Ok(sqlx::query_as::<_, RegionsTopRow>(sql)
+            .bind(i64::try_from(limit).unwrap_or(i64::MAX))
+            .bind(ties == Ties::Dense)
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn gift_totals(&self, filter: &OrderFilter) -> Result<Vec<GiftTotalRow>, AppError> {
+        let sql = format!(
+            "SELECT o.gift_name AS gift, SUM(o.quantity) AS total
+            FROM orders o
+            LEFT JOIN regions r ON o.region_id = r.id
+            {PG_FILTER}
+            GROUP BY o.gift_name
+            ORDER BY total DESC, gift ASC"
+        );
+
+        Ok(sqlx::query_as::<_, GiftTotalRow>(&sql)
+            .bind(&filter.region)
+            .bind(&filter.gift)
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn region_totals(&self, filter: &OrderFilter) -> Result<Vec<RegionsTotalRow>, AppError> {
+        let sql = format!(
+            "SELECT r.name AS region, SUM(o.quantity) AS total
+            FROM orders o
+            INNER JOIN regions r ON o.region_id = r.id
+            {PG_FILTER}
+            GROUP BY r.name
+            ORDER BY total DESC, region ASC"
+        );
+
+        Ok(sqlx::query_as::<_, RegionsTotalRow>(&sql)
+            .bind(&filter.region)
+            .bind(&filter.gift)
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn region_gift_totals(
+        &self,
+        filter: &OrderFilter,
+    ) -> Result<Vec<RegionGiftRow>, AppError> {
+        let sql = format!(
+            "SELECT r.name AS region, o.gift_name AS gift, SUM(o.quantity) AS total
+            FROM orders o
+            INNER JOIN regions r ON o.region_id = r.id
+            {PG_FILTER}
+            GROUP BY r.name, o.gift_name
+            ORDER BY region ASC, gift ASC"
+        );
+
+        Ok(sqlx::query_as::<_, RegionGiftRow>(&sql)
+            .bind(&filter.region)
+            .bind(&filter.gift)
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn quantity_percentiles(
+        &self,
+        filter: &OrderFilter,
+        percentiles: &[f64],
+    ) -> Result<Option<Vec<f64>>, AppError> {
+        let sql = format!(
+            "SELECT percentile_cont($3::FLOAT8[]) WITHIN GROUP (ORDER BY o.quantity)
+            FROM orders o
+            LEFT JOIN regions r ON o.region_id = r.id
+            {PG_FILTER}"
+        );
+
+        Ok(sqlx::query_scalar::<_, Option<Vec<f64>>>(&sql)
+            .bind(&filter.region)
+            .bind(&filter.gift)
+            .bind(percentiles)
+            .fetch_one(&self.pool)
+            .await?)
+    }
+
+    fn export(&self) -> BoxStream<'static, Result<ExportRow, AppError>> {
+        // The row stream borrows the pool, so it is driven by a task that hands
+        // rows over through a bounded channel, keeping memory use flat.
+        let pool = self.pool.clone();
+        let (tx, rx) = tokio::sync::mpsc::channel(64);
+        tokio::spawn(async move {
+            let sql = "SELECT o.id::INT8 AS order_id, o.region_id::INT8 AS region_id,
+                    r.name AS region, o.gift_name, o.quantity::INT8 AS quantity
+                FROM orders o
+                JOIN regions r ON o.region_id = r.id
+                ORDER BY o.id";
+            let mut rows = sqlx::query_as::<_, ExportRow>(sql).fetch(&pool);
+            while let Some(row) = rows.next().await {
+                if tx.send(row.map_err(AppError::from)).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        futures::stream::unfold(
+            rx,
+            |mut rx| async move { rx.recv().await.map(|row| (row, rx)) },
+        )
+        .boxed()
+    }
+}
+
+#[derive(Default)]
+struct MemoryData {
+    orders: BTreeMap<i64, Order>,
+    regions: BTreeMap<i64, Region>,
+}
+
+/// Keeps everything in process memory, for running without a database.
+#[derive(Default)]
+pub struct MemoryOrderStore {
+    data: Mutex<MemoryData>,
+}
+
+impl MemoryOrderStore {
+    fn data(&self) -> std::sync::MutexGuard<'_, MemoryData> {
+        self.data.lock().expect("mutex was poisoned")
+    }
+}
+
+impl MemoryData {
+    /// Orders matching `filter`, with the name of their region if it exists.
+    fn filtered<'a>(
+        &'a self,
+        filter: &'a OrderFilter,
+    ) -> impl Iterator<Item = (Option<&'a str>, &'a Order)> + 'a {
+        self.orders
+            .values()
+            .map(|order| {
+                let region = self.regions.get(&order.region_id);
+                (region.map(|r| r.name.as_str()), order)
+            })
+            .filter(|(region, order)| {
+                filter
+                    .region
+                    .as_deref()
+                    .is_none_or(|name| *region == Some(name))
+                    && filter
+                        .gift
+                        .as_ref()
+                        .is_none_or(|gift| order.gift_name == *gift)
+            })
+    }
+}
+
+/// Keeps the first `limit` ranks of `ranked` gifts.
+fn top(ranked: Vec<(String, i64)>, limit: usize, ties: Ties) -> Vec<String> {
+    let mut gifts = vec![];
+    let mut rank = 0;
+    let mut prev = None;
+    for (gift, quantity) in ranked {
+        if ties == Ties::Strict || prev != Some(quantity) {
+            rank += 1;
+        }
+        if rank > limit {
+            break;
+        }
+        prev = Some(quantity);
+        gifts.push(gift);
+    }
+
+    gifts
+}
+
+/// Sums quantities per key, sorted by quantity desc then key asc.
+fn ranked<K: Ord>(items: impl Iterator<Item = (K, i64)>) -> Vec<(K, i64)> {
+    let mut totals = BTreeMap::new();
+    for (key, quantity) in items {
+        *totals.entry(key).or_insert(0) += quantity;
+    }
+
+    let mut totals = totals.into_iter().collect::<Vec<_>>();
+    totals.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    totals
+}
+
+#[async_trait]
+impl OrderStore for MemoryOrderStore {
+    async fn echo(&self, n: i64) -> Result<i64, AppError> {
+        Ok(n)
+    }
+
+    async fn reset(&self) -> Result<(), AppError> {
+        *self.data() = MemoryData::default();
+
+        Ok(())
+    }
+
+    async fn orders(
+        &self,
+        orders: Vec<Order>,
+        mode: InsertMode,
+        regions: OrderRegions,
+    ) -> Result<InsertReport, AppError> {
+        let mut data = self.data();
+        let existing = data.orders.keys().copied().collect();
+        let known_regions = match regions {
+            OrderRegions::Any => None,
+            OrderRegions::Known => Some(data.regions.keys().copied().collect()),
+        };
+        let report = validate_orders(&orders, &existing, known_regions.as_ref(), mode)?;
+
+        for order in orders {
+            data.orders.insert(order.id, order);
+        }
+
+        Ok(report)
+    }
+
+    async fn orders_total(&self) -> Result<i64, AppError> {
+        Ok(self.data().orders.values().map(|o| o.quantity).sum())
+    }
+
+    async fn orders_popular(&self) -> Result<Option<String>, AppError> {
+        let data = self.data();
+        let gifts = ranked(
+            data.orders
+                .values()
+                .map(|o| (o.gift_name.clone(), o.quantity)),
+        );
+
+        Ok(gifts.into_iter().next().map(|(gift, _)| gift))
+    }
+
+    async fn regions(
+        &self,
+        regions: Vec<Region>,
+        mode: InsertMode,
+    ) -> Result<InsertReport, AppError> {
+        let mut data = self.data();
+        let existing = data.regions.keys().copied().collect();
+        let report = validate_regions(&regions, &existing, mode)?;
+
+        for region in regions {
+            data.regions.insert(region.id, region);
+        }
+
+        Ok(report)
+    }
+
+    async fn regions_total(&self) -> Result<Vec<RegionsTotalRow>, AppError> {
+        let data = self.data();
+        let mut totals = BTreeMap::new();
+        for order in data.orders.values() {
+            if let Some(region) = data.regions.get(&order.region_id) {
+                *totals.entry(region.name.clone()).or_insert(0) += order.quantity;
+            }
+        }
+
+        Ok(totals
+            .into_iter()
+            .map(|(region, total)| RegionsTotalRow { region, total })
+            .collect())
+    }
+
+    async fn regions_top_list(
+        &self,
+        limit: usize,
+        ties: Ties,
+    ) -> Result<Vec<RegionsTopRow>, AppError> {
+        let data = self.data();
+        let mut by_region: BTreeMap<String, HashMap<String, i64>> = BTreeMap::new();
+        for region in data.regions.values() {
+            by_region.entry(region.name.clone()).or_default();
+        }
+        for order in data.orders.values() {
+            if let Some(region) = data.regions.get(&order.region_id) {
+                *by_region
+                    .entry(region.name.clone())
+                    .or_default()
+                    .entry(order.gift_name.clone())
+                    .or_insert(0) += order.quantity;
+            }
+        }
+
+        Ok(by_region
+            .into_iter()
+            .map(|(region, gifts)| RegionsTopRow {
+                region,
+                top_gifts: sqlx::types::Json(top(ranked(gifts.into_iter()), limit, ties)),
+            })
+            .collect())
+    }
+
+    async fn gift_totals(&self, filter: &OrderFilter) -> Result<Vec<GiftTotalRow>, AppError> {
+        let data = self.data();
+        let gifts = ranked(
+            data.filtered(filter)
+                .map(|(_, o)| (o.gift_name.clone(), o.quantity)),
+        );
+
+        Ok(gifts
+            .into_iter()
+            .map(|(gift, total)| GiftTotalRow { gift, total })
+            .collect())
+    }
+
+    async fn region_totals(&self, filter: &OrderFilter) -> Result<Vec<RegionsTotalRow>, AppError> {
+        let data = self.data();
+        let regions = ranked(
+            data.filtered(filter)
+                .filter_map(|(region, o)| Some((region?.to_string(), o.quantity))),
+        );
+
+        Ok(regions
+            .into_iter()
+            .map(|(region, total)| RegionsTotalRow { region, total })
+            .collect())
+    }
+
+    async fn region_gift_totals(
+        &self,
+        filter: &OrderFilter,
+    ) -> Result<Vec<RegionGiftRow>, AppError> {
+        let data = self.data();
+        let mut totals = BTreeMap::new();
+        for (region, order) in data.filtered(filter) {
+            if let Some(region) = region {
+                *totals
+                    .entry((region.to_string(), order.gift_name.clone()))
+                    .or_insert(0) += order.quantity;
+            }
+        }
+
+        Ok(totals
+            .into_iter()
+            .map(|((region, gift), total)| RegionGiftRow {
+                region,
+                gift,
+                total,
+            })
+            .collect())
+    }
+
+    async fn quantity_percentiles(
+        &self,
+        filter: &OrderFilter,
+        percentiles: &[f64],
+    ) -> Result<Option<Vec<f64>>, AppError> {
+        let data = self.data();
+        let mut quantities = data
+            .filtered(filter)
+            .map(|(_, o)| o.quantity as f64)
+            .collect::<Vec<_>>();
+        if quantities.is_empty() {
+            return Ok(None);
+        }
+        quantities.sort_by(f64::total_cmp);
+
+        Ok(Some(
+            percentiles
+                .iter()
+                .map(|p| percentile_cont(&quantities, *p))
+                .collect(),
+        ))
+    }
+    fn export(&self) -> BoxStream<'static, Result<ExportRow, AppError>> {
+        let data = self.data();
+        let rows = data
+            .orders
+            .values()
+            .filter_map(|order| {
+                let region = data.regions.get(&order.region_id)?;
+                Some(Ok(ExportRow {
+                    order_id: order.id,
+                    region_id: order.region_id,
+                    region: region.name.clone(),
+                    gift_name: order.gift_name.clone(),
+                    quantity: order.quantity,
+                }))
+            })
+            .collect::<Vec<_>>();
+
+        futures::stream::iter(rows).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(id: i64, region_id: i64) -> Order {
+        Order {
+            id,
+            region_id,
+            gift_name: "Toy Train".to_string(),
+            quantity: 2,
+        }
+    }
+
+    fn details(err: AppError) -> Vec<(usize, String)> {
+        match err {
+            AppError::InvalidItems(errors) => errors
+                .into_iter()
+                .map(|err| (err.index, err.detail))
+                .collect(),
+            err => panic!("expected invalid items, got {err}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn only_known_regions_are_required() {
+        let store = MemoryOrderStore::default();
+        let north = Region {
+            id: 1,
+            name: "North Pole".to_string(),
+        };
+        store
+            .regions(vec![north], InsertMode::Insert)
+            .await
+            .unwrap();
+
+        let orders = vec![order(1, 1), order(2, 7)];
+        let err = store
+            .orders(orders.clone(), InsertMode::Insert, OrderRegions::Known)
+            .await
+            .unwrap_err();
+        assert_eq!(details(err), [(1, "unknown region 7".to_string())]);
+        assert_eq!(store.orders_total().await.unwrap(), 0);
+
+        let report = store
+            .orders(orders, InsertMode::Insert, OrderRegions::Any)
+            .await
+            .unwrap();
+        assert_eq!(report.inserted, 2);
+    }
+
+    #[tokio::test]
+    async fn names_must_fit_their_columns() {
+        let store = MemoryOrderStore::default();
+        let mut long = order(1, 1);
+        long.gift_name = "é".repeat(MAX_NAME_LEN + 1);
+        let mut longest = order(2, 1);
+        longest.gift_name = "é".repeat(MAX_NAME_LEN);
+        let err = store
+            .orders(vec![long, longest], InsertMode::Insert, OrderRegions::Any)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            details(err),
+            [(
+                0,
+                "gift_name is 51 characters long, at most 50 are allowed".to_string()
+            )]
+        );
+
+        let region = Region {
+            id: 1,
+            name: "x".repeat(MAX_NAME_LEN + 1),
+        };
+        let err = store
+            .regions(vec![region], InsertMode::Insert)
+            .await
+            .unwrap_err();
+        assert_eq!(details(err).len(), 1);
+    }
+}
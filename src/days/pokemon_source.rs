@@ -0,0 +1,346 @@
+use async_trait::async_trait;
+use log::{info, warn};
+use reqwest::StatusCode;
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use crate::clock::Clock;
+use crate::error::{AppError, ConfigError};
+use crate::lru::LruMap;
+
+/// The part of a PokéAPI `pokemon` resource the days use.
+#[derive(serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct Pokemon {
+    /// In hectograms.
+    pub weight: f64,
+}
+
+/// Where day 8 looks Pokémon up, by Pokédex number or name.
+#[async_trait]
+pub trait PokemonSource: Send + Sync {
+    /// `None` if there is no such Pokémon.
+    async fn find(&self, id: &str) -> Result<Option<Pokemon>, AppError>;
+
+    /// Like `find`, failing with [`AppError::NotFound`] for unknown Pokémon.
+    async fn get(&self, id: &str) -> Result<Pokemon, AppError> {
+        if !is_valid_id(id) {
+            return Err(AppError::NotFound(format!("no pokemon '{id}'")));
+        }
+
+        self.find(&id.to_lowercase())
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("no pokemon '{id}'")))
+    }
+}
+
+/// Pokédex numbers and names only, so ids are safe in URLs and file names.
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= 64 && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+const DEFAULT_API_URL: &str = "https://pokeapi.co/api/v2";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const RETRIES: u32 = 2;
+/// Doubled after every failed attempt.
+const RETRY_DELAY: Duration = Duration::from_millis(200);
+const CACHE_SIZE: usize = 256;
+const CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Picks the source from `POKEMON_SOURCE` (`http` or `fixtures`), `http` by
+/// default. The HTTP source queries `POKEAPI_URL` (PokéAPI by default) through a
+/// cache, the fixture source reads `<id>.json` files from `POKEMON_FIXTURES`
+/// (`fixtures/pokemon` by default).
+pub fn from_env(clock: Arc<dyn Clock>) -> Result<Arc<dyn PokemonSource>, ConfigError> {
+    match std::env::var("POKEMON_SOURCE").ok().as_deref() {
+        None | Some("http") => {
+            let base_url =
+                std::env::var("POKEAPI_URL").unwrap_or_else(|_| DEFAULT_API_URL.to_string());
+            let http = HttpPokemonSource::new(base_url)?;
+            Ok(Arc::new(CachedPokemonSource::new(
+                Arc::new(http),
+                clock,
+                CACHE_SIZE,
+                CACHE_TTL,
+            )))
+        }
+        Some("fixtures") => {
+            let dir = std::env::var("POKEMON_FIXTURES")
+                .unwrap_or_else(|_| "fixtures/pokemon".to_string());
+            Ok(Arc::new(FixturePokemonSource { dir: dir.into() }))
+        }
+        Some(kind) => Err(ConfigError(format!("unknown pokemon source {kind}"))),
+    }
+}
+
+/// PokéAPI, or anything serving `<base_url>/pokemon/<id>` the same way.
+pub struct HttpPokemonSource {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl HttpPokemonSource {
+    pub fn new(base_url: String) -> Result<Self, ConfigError> {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .connect_timeout(CONNECT_TIMEOUT)
+            .build()
+            .map_err(|err| ConfigError(format!("cannot build the PokéAPI client: {err}")))?;
+
+        Ok(HttpPokemonSource {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        })
+    }
+
+    /// One attempt. Errors worth retrying are the `Err(true)` ones.
+    async fn fetch(&self, url: &str) -> Result<Option<Pokemon>, (bool, AppError)> {
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .map_err(|err| (true, AppError::Upstream(err.to_string())))?;
+
+        let status = response.status();
+        if status == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+            return Err((true, AppError::Upstream(format!("{url} answered {status}"))));
+        }
+
+        let body = response
+            .error_for_status()
+            .map_err(|err| (false, AppError::Upstream(err.to_string())))?
+            .text()
+            .await
+            .map_err(|err| (true, AppError::Upstream(err.to_string())))?;
+
+        serde_json::from_str(&body)
+            .map(Some)
+            .map_err(|err| (false, AppError::Upstream(err.to_string())))
+    }
+}
+
+#[async_trait]
+impl PokemonSource for HttpPokemonSource {
+    async fn find(&self, id: &str) -> Result<Option<Pokemon>, AppError> {
+        let url = format!("{}/pokemon/{id}", self.base_url);
+        let mut delay = RETRY_DELAY;
+        let mut attempt = 0;
+        loop {
+            match self.fetch(&url).await {
+                Ok(pokemon) => return Ok(pokemon),
+                Err((true, err)) if attempt < RETRIES => {
+                    warn!("fetching {url} failed, retrying in {delay:?}: {err}");
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                    attempt += 1;
+                }
+                Err((_, err)) => return Err(err),
+            }
+        }
+    }
+}
+
+/// Pokémon read from `<dir>/<id>.json`, for running without network.
+pub struct FixturePokemonSource {
+    dir: PathBuf,
+}
+
+#[async_trait]
+impl PokemonSource for FixturePokemonSource {
+    async fn find(&self, id: &str) -> Result<Option<Pokemon>, AppError> {
+        let path = self.dir.join(format!("{id}.json"));
+        let content = match tokio::fs::read(&path).await {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(AppError::Internal(format!(
+                    "cannot read {}: {err}",
+                    path.display()
+                )))
+            }
+        };
+
+        serde_json::from_slice(&content)
+            .map(Some)
+            .map_err(|err| AppError::Internal(format!("invalid {}: {err}", path.display())))
+    }
+}
+
+struct CacheEntry {
+    pokemon: Option<Pokemon>,
+    fetched_at: SystemTime,
+}
+
+/// Remembers what another source answered, unknown Pokémon included, for `ttl`.
+/// Holds at most `size` Pokémon, dropping the least recently used one.
+pub struct CachedPokemonSource {
+    inner: Arc<dyn PokemonSource>,
+    clock: Arc<dyn Clock>,
+    ttl: Duration,
+    cache: Mutex<LruMap<String, CacheEntry>>,
+}
+
+impl CachedPokemonSource {
+    pub fn new(
+        inner: Arc<dyn PokemonSource>,
+        clock: Arc<dyn Clock>,
+        size: usize,
+        ttl: Duration,
+    ) -> Self {
+        CachedPokemonSource {
+            inner,
+            clock,
+            ttl,
+            cache: Mutex::new(LruMap::new(size)),
+        }
+    }
+
+    fn cache(&self) -> std::sync::MutexGuard<'_, LruMap<String, CacheEntry>> {
+        self.cache.lock().expect("mutex was poisoned")
+    }
+
+    fn is_fresh(&self, entry: &CacheEntry, now: SystemTime) -> bool {
+        now.duration_since(entry.fetched_at)
+            .is_ok_and(|age| age < self.ttl)
+    }
+}
+
+#[async_trait]
+impl PokemonSource for CachedPokemonSource {
+    async fn find(&self, id: &str) -> Result<Option<Pokemon>, AppError> {
+        let now = self.clock.now();
+        if let Some(entry) = self.cache().get(id) {
+            if self.is_fresh(entry, now) {
+                return Ok(entry.pokemon.clone());
+            }
+        }
+
+        let pokemon = self.inner.find(id).await?;
+
+        let mut cache = self.cache();
+        cache.retain(|_, entry| self.is_fresh(entry, now));
+        let entry = CacheEntry {
+            pokemon: pokemon.clone(),
+            fetched_at: now,
+        };
+        if let Some(oldest) = cache.insert(id.to_string(), entry) {
+            info!("dropping pokemon {oldest} from the cache");
+        }
+
+        Ok(pokemon)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::FakeClock;
+
+    fn fixtures() -> FixturePokemonSource {
+        FixturePokemonSource {
+            dir: PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/pokemon"),
+        }
+    }
+
+    /// Knows `pikachu` only, and remembers what it was asked. Fails for `missingno`.
+    #[derive(Default)]
+    struct CountingSource {
+        asked: Mutex<Vec<String>>,
+    }
+
+    impl CountingSource {
+        fn asked(&self) -> Vec<String> {
+            self.asked.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl PokemonSource for CountingSource {
+        async fn find(&self, id: &str) -> Result<Option<Pokemon>, AppError> {
+            self.asked.lock().unwrap().push(id.to_string());
+            match id {
+                "missingno" => Err(AppError::Upstream("glitch".to_string())),
+                "pikachu" => Ok(Some(Pokemon { weight: 60.0 })),
+                _ => Ok(None),
+            }
+        }
+    }
+
+    fn cached(size: usize) -> (Arc<CountingSource>, Arc<FakeClock>, CachedPokemonSource) {
+        let inner = Arc::new(CountingSource::default());
+        let clock = Arc::new(FakeClock::default());
+        clock.freeze(Some(SystemTime::UNIX_EPOCH));
+        let source =
+            CachedPokemonSource::new(inner.clone(), clock.clone(), size, Duration::from_secs(60));
+
+        (inner, clock, source)
+    }
+
+    #[tokio::test]
+    async fn fixtures_are_found_by_number_or_name() {
+        let source = fixtures();
+        assert_eq!(source.get("25").await.unwrap().weight, 60.0);
+        assert_eq!(source.get("Pikachu").await.unwrap().weight, 60.0);
+        assert!(matches!(
+            source.get("151").await,
+            Err(AppError::NotFound(_))
+        ));
+        assert!(matches!(
+            source.get("../25").await,
+            Err(AppError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn answers_are_cached_until_they_expire() {
+        let (inner, clock, source) = cached(8);
+        source.get("pikachu").await.unwrap();
+        clock.advance(Duration::from_secs(59)).unwrap();
+        source.get("pikachu").await.unwrap();
+        assert_eq!(inner.asked(), ["pikachu"]);
+
+        clock.advance(Duration::from_secs(1)).unwrap();
+        source.get("pikachu").await.unwrap();
+        assert_eq!(inner.asked(), ["pikachu", "pikachu"]);
+    }
+
+    #[tokio::test]
+    async fn unknown_pokemon_are_cached_too() {
+        let (inner, _, source) = cached(8);
+        for _ in 0..2 {
+            assert!(matches!(
+                source.get("agumon").await,
+                Err(AppError::NotFound(_))
+            ));
+        }
+        assert_eq!(inner.asked(), ["agumon"]);
+    }
+
+    #[tokio::test]
+    async fn errors_are_not_cached() {
+        let (inner, _, source) = cached(8);
+        assert!(source.get("missingno").await.is_err());
+        assert!(source.get("missingno").await.is_err());
+        assert_eq!(inner.asked(), ["missingno", "missingno"]);
+    }
+
+    #[tokio::test]
+    async fn the_least_recently_used_pokemon_is_dropped() {
+        let (inner, _, source) = cached(2);
+        for id in ["pikachu", "agumon", "pikachu", "gabumon"] {
+            source.find(id).await.unwrap();
+        }
+        assert_eq!(inner.asked(), ["pikachu", "agumon", "gabumon"]);
+
+        source.find("pikachu").await.unwrap();
+        source.find("agumon").await.unwrap();
+        assert_eq!(inner.asked(), ["pikachu", "agumon", "gabumon", "agumon"]);
+    }
+}
@@ -0,0 +1,389 @@
+//! Ingredient amounts with an optional unit, kept as exact decimals.
+//!
+//! An amount is either a bare JSON number, counting pieces like the original
+//! recipes, or a string such as `"1.5 kg"` or `"2 cups"`. Amounts of the same
+//! dimension convert into each other through [`Unit::factor`], others don't.
+
+use rust_decimal::Decimal;
+use serde_json::{Number, Value};
+use std::str::FromStr;
+
+use crate::error::AppError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dimension {
+    Mass,
+    Volume,
+    Count,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Gram,
+    Kilogram,
+    Milliliter,
+    Liter,
+    /// A metric cup, so that cups and milliliters convert exactly.
+    Cup,
+    Piece,
+}
+
+impl Unit {
+    fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "g" | "gram" | "grams" => Some(Unit::Gram),
+            "kg" | "kilogram" | "kilograms" => Some(Unit::Kilogram),
+            "ml" | "milliliter" | "milliliters" | "millilitre" | "millilitres" => {
+                Some(Unit::Milliliter)
+            }
+            "l" | "liter" | "liters" | "litre" | "litres" => Some(Unit::Liter),
+            "cup" | "cups" => Some(Unit::Cup),
+            "pc" | "pcs" | "piece" | "pieces" => Some(Unit::Piece),
+            _ => None,
+        }
+    }
+
+    fn symbol(&self) -> &'static str {
+        match self {
+            Unit::Gram => "g",
+            Unit::Kilogram => "kg",
+            Unit::Milliliter => "ml",
+            Unit::Liter => "l",
+            Unit::Cup => "cups",
+            Unit::Piece => "pieces",
+        }
+    }
+
+    fn dimension(&self) -> Dimension {
+        match self {
+            Unit::Gram | Unit::Kilogram => Dimension::Mass,
+            Unit::Milliliter | Unit::Liter | Unit::Cup => Dimension::Volume,
+            Unit::Piece => Dimension::Count,
+        }
+    }
+
+    /// One of this unit in grams, milliliters or pieces.
+    fn factor(&self) -> Decimal {
+        match self {
+            Unit::Gram | Unit::Milliliter | Unit::Piece => Decimal::ONE,
+            Unit::Kilogram | Unit::Liter => Decimal::ONE_THOUSAND,
+            Unit::Cup => Decimal::from(250),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quantity {
+    pub amount: Decimal,
+    /// None for a bare number of pieces.
+    pub unit: Option<Unit>,
+}
+
+fn parse_decimal(s: &str) -> Option<Decimal> {
+    Decimal::from_str(s)
+        .or_else(|_| Decimal::from_scientific(s))
+        .ok()
+}
+
+fn overflow(name: &str) -> AppError {
+    AppError::Unprocessable(format!("'{name}' is too large an amount"))
+}
+
+impl Quantity {
+    pub fn zero(unit: Option<Unit>) -> Self {
+        Quantity {
+            amount: Decimal::ZERO,
+            unit,
+        }
+    }
+
+    /// Reads the amount of ingredient `name`.
+    pub fn from_json(name: &str, value: &Value) -> Result<Self, AppError> {
+        let invalid = || AppError::Unprocessable(format!("'{name}' is not an amount: {value}"));
+        let quantity = match value {
+            Value::Number(n) => Quantity {
+                amount: parse_decimal(&n.to_string()).ok_or_else(invalid)?,
+                unit: None,
+            },
+            Value::String(s) => {
+                let s = s.trim();
+                // The unit is the trailing letters, an exponent's `e` is not one.
+                let split = s.trim_end_matches(char::is_alphabetic).len();
+                let (amount, unit) = s.split_at(split);
+                let unit = match unit.trim() {
+                    "" => None,
+                    unit => Some(Unit::parse(unit).ok_or_else(|| {
+                        AppError::Unprocessable(format!("'{name}' has an unknown unit '{unit}'"))
+                    })?),
+                };
+                Quantity {
+                    amount: parse_decimal(amount.trim()).ok_or_else(invalid)?,
+                    unit,
+                }
+            }
+            _ => return Err(invalid()),
+        };
+        if quantity.amount.is_sign_negative() && !quantity.amount.is_zero() {
+            return Err(invalid());
+        }
+
+        Ok(quantity)
+    }
+
+    /// The amount as it was read: a bare number, or a string with its unit.
+    pub fn to_json(&self) -> Value {
+        let amount = self.amount.normalize().to_string();
+        match self.unit {
+            None => Number::from_str(&amount)
+                .map(Value::Number)
+                .unwrap_or(Value::String(amount)),
+            Some(unit) => Value::String(format!("{amount} {}", unit.symbol())),
+        }
+    }
+
+    fn dimension(&self) -> Dimension {
+        self.unit.map_or(Dimension::Count, |unit| unit.dimension())
+    }
+
+    fn factor(&self) -> Decimal {
+        self.unit.map_or(Decimal::ONE, |unit| unit.factor())
+    }
+
+    /// The amount in grams, milliliters or pieces.
+    fn base(&self, name: &str) -> Result<Decimal, AppError> {
+        self.amount
+            .checked_mul(self.factor())
+            .ok_or_else(|| overflow(name))
+    }
+
+    fn check_compatible(&self, name: &str, other: &Quantity) -> Result<(), AppError> {
+        if self.dimension() == other.dimension() {
+            return Ok(());
+        }
+        let describe = |quantity: &Quantity| quantity.unit.map_or("pieces", |unit| unit.symbol());
+
+        Err(AppError::Unprocessable(format!(
+            "'{name}' cannot be converted from {} to {}",
+            describe(other),
+            describe(self)
+        )))
+    }
+
+    /// How many whole times `need` fits in this amount of ingredient `name`, or
+    /// nothing if `need` is zero.
+    pub fn fits(&self, name: &str, need: &Quantity) -> Result<Option<Decimal>, AppError> {
+        self.check_compatible(name, need)?;
+        if need.amount.is_zero() {
+            return Ok(None);
+        }
+        let (have, need) = (self.base(name)?, need.base(name)?);
+
+        // The quotient is rounded to 28 digits, make sure its floor is exact.
+        let mut times = have
+            .checked_div(need)
+            .ok_or_else(|| overflow(name))?
+            .floor();
+        let fits = |times: Decimal| times.checked_mul(need).is_some_and(|taken| taken <= have);
+        while times > Decimal::ZERO && !fits(times) {
+            times -= Decimal::ONE;
+        }
+        while let Some(next) = times.checked_add(Decimal::ONE).filter(|&next| fits(next)) {
+            times = next;
+        }
+
+        Ok(Some(times))
+    }
+
+    /// What is left after taking `need` `times` times, in this amount's unit.
+    pub fn take(&self, name: &str, need: &Quantity, times: Decimal) -> Result<Quantity, AppError> {
+        self.check_compatible(name, need)?;
+        let taken = need
+            .base(name)?
+            .checked_mul(times)
+            .ok_or_else(|| overflow(name))?;
+        let left = self
+            .base(name)?
+            .checked_sub(taken)
+            .filter(|left| !left.is_sign_negative() || left.is_zero())
+            .ok_or_else(|| AppError::Unprocessable(format!("not enough '{name}'")))?;
+
+        Ok(Quantity {
+            amount: left / self.factor(),
+            unit: self.unit,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn quantity(value: Value) -> Quantity {
+        Quantity::from_json("flour", &value).unwrap()
+    }
+
+    fn decimal(s: &str) -> Decimal {
+        Decimal::from_str(s).unwrap()
+    }
+
+    /// How many times `need` fits in `have`, and what is left after taking it
+    /// that many times.
+    fn bake(have: Value, need: Value) -> (Decimal, Value) {
+        let (have, need) = (quantity(have), quantity(need));
+        let times = have.fits("flour", &need).unwrap().unwrap();
+        let left = have.take("flour", &need, times).unwrap();
+
+        (times, left.to_json())
+    }
+
+    #[test]
+    fn amounts_are_read_with_or_without_units() {
+        let read = |value| {
+            let quantity = quantity(value);
+            (quantity.amount, quantity.unit)
+        };
+        assert_eq!(read(json!(3)), (decimal("3"), None));
+        assert_eq!(read(json!(0.25)), (decimal("0.25"), None));
+        assert_eq!(read(json!("1.5kg")), (decimal("1.5"), Some(Unit::Kilogram)));
+        assert_eq!(read(json!(" 2 Cups ")), (decimal("2"), Some(Unit::Cup)));
+        assert_eq!(read(json!("1e3 g")), (decimal("1000"), Some(Unit::Gram)));
+        assert_eq!(read(json!("4")), (decimal("4"), None));
+        assert_eq!(quantity(json!("1.50 kg")).to_json(), json!("1.5 kg"));
+        assert_eq!(quantity(json!(2)).to_json(), json!(2));
+    }
+
+    #[test]
+    fn invalid_amounts_are_rejected() {
+        for value in [
+            json!(-1),
+            json!("-0.5 kg"),
+            json!("3 parsecs"),
+            json!("kg"),
+            json!("a lot"),
+            json!(true),
+            json!([1]),
+        ] {
+            let err = Quantity::from_json("flour", &value).unwrap_err();
+            assert!(matches!(err, AppError::Unprocessable(_)), "{value}: {err}");
+        }
+    }
+
+    #[test]
+    fn masses_convert_both_ways() {
+        assert_eq!(
+            bake(json!("1.5 kg"), json!("250 g")),
+            (decimal("6"), json!("0 kg"))
+        );
+        assert_eq!(
+            bake(json!("1.6 kg"), json!("250 g")),
+            (decimal("6"), json!("0.1 kg"))
+        );
+        assert_eq!(
+            bake(json!("1500 g"), json!("0.4 kg")),
+            (decimal("3"), json!("300 g"))
+        );
+    }
+
+    #[test]
+    fn volumes_convert_both_ways() {
+        assert_eq!(
+            bake(json!("2 cups"), json!("150 ml")),
+            (decimal("3"), json!("0.2 cups"))
+        );
+        assert_eq!(
+            bake(json!("600 ml"), json!("1 cup")),
+            (decimal("2"), json!("100 ml"))
+        );
+        assert_eq!(
+            bake(json!("1 l"), json!("3 cups")),
+            (decimal("1"), json!("0.25 l"))
+        );
+    }
+
+    #[test]
+    fn remainders_do_not_drift() {
+        assert_eq!(bake(json!(1), json!(0.3)), (decimal("3"), json!(0.1)));
+        assert_eq!(
+            bake(json!("1 kg"), json!("0.3 kg")),
+            (decimal("3"), json!("0.1 kg"))
+        );
+    }
+
+    #[test]
+    fn fits_is_the_exact_floor() {
+        let amounts = [
+            "1",
+            "0.3",
+            "7",
+            "0.1",
+            "0.3333333333333333333333333333",
+            "0.3333333333333333333333333334",
+            "0.6666666666666666666666666667",
+            "1.0000000000000000000000000001",
+            "1000000000000000000000000000",
+        ];
+        for have in amounts {
+            for need in amounts {
+                let times = quantity(json!(have))
+                    .fits("flour", &quantity(json!(need)))
+                    .unwrap()
+                    .unwrap();
+                let (have, need) = (decimal(have), decimal(need));
+                let taken = |times: Decimal| times.checked_mul(need);
+                assert!(
+                    taken(times).is_some_and(|taken| taken <= have),
+                    "{have} / {need}"
+                );
+                assert!(
+                    times
+                        .checked_add(Decimal::ONE)
+                        .and_then(taken)
+                        .is_none_or(|taken| taken > have),
+                    "{have} / {need}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn nothing_needed_fits_without_limit() {
+        let fits = quantity(json!("1 kg")).fits("flour", &quantity(json!("0 g")));
+        assert_eq!(fits.unwrap(), None);
+    }
+
+    #[test]
+    fn incompatible_units_are_rejected() {
+        for (have, need) in [
+            (json!("1 kg"), json!("1 cup")),
+            (json!("500 ml"), json!("2 g")),
+            (json!(3), json!("1 g")),
+            (json!("2 pieces"), json!("1 l")),
+        ] {
+            let (have, need) = (quantity(have), quantity(need));
+            let err = have.fits("flour", &need).unwrap_err();
+            assert!(matches!(err, AppError::Unprocessable(_)), "{err}");
+            assert!(have.take("flour", &need, Decimal::ONE).is_err());
+        }
+        // Bare numbers are pieces.
+        assert_eq!(bake(json!(5), json!("2 pieces")), (decimal("2"), json!(1)));
+    }
+
+    #[test]
+    fn taking_too_much_fails() {
+        let (have, need) = (quantity(json!("1 kg")), quantity(json!("300 g")));
+        let err = have.take("flour", &need, decimal("4")).unwrap_err();
+        assert!(matches!(err, AppError::Unprocessable(_)), "{err}");
+    }
+
+    #[test]
+    fn overflowing_amounts_are_rejected() {
+        let have = quantity(json!("79228162514264337593543950335 kg"));
+        let need = quantity(json!("1 g"));
+        assert!(have.fits("flour", &need).is_err());
+
+        let have = quantity(json!("79228162514264337593543950335"));
+        let fits = have.fits("flour", &quantity(json!(1))).unwrap();
+        assert_eq!(fits, Some(Decimal::MAX));
+    }
+}
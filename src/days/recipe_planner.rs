@@ -0,0 +1,522 @@
+//! Picks how many batches of each recipe to bake from a shared pantry so that
+//! their total value is the highest.
+//!
+//! This is an integer linear program: maximize `value · batches` under
+//! `needs · batches <= pantry`. It is solved by branch and bound over the linear
+//! relaxation, each node bounding some recipes' batches. As every need is
+//! non-negative, rounding a relaxed solution down always fits in the pantry,
+//! which gives a good mix to compare the other nodes against.
+
+use std::time::{Duration, Instant};
+
+/// A recipe as the planner sees it: the amount of each ingredient one batch
+/// needs, indexed like the pantry.
+#[derive(Debug, Clone)]
+pub struct Recipe {
+    pub needs: Vec<u64>,
+    pub value: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mix {
+    /// Batches of each recipe, in the order they were given.
+    pub batches: Vec<u64>,
+    pub value: u128,
+    /// False when the search gave up, `batches` then being the best mix found.
+    pub optimal: bool,
+}
+
+/// Nodes solved before returning the best mix found so far.
+const NODE_BUDGET: usize = 20_000;
+/// Time spent searching before returning the best mix found so far.
+const TIME_BUDGET: Duration = Duration::from_millis(500);
+/// Simplex pivots per node before its bound is given up on.
+const MAX_PIVOTS: usize = 1_000;
+const EPSILON: f64 = 1e-9;
+/// Relative error allowed on the relaxation's results.
+const TOLERANCE: f64 = 1e-12;
+
+/// How much searching [`plan`] may do before settling for the best mix found.
+#[derive(Debug, Clone, Copy)]
+pub struct Budget {
+    pub nodes: usize,
+    pub time: Duration,
+}
+
+impl Default for Budget {
+    fn default() -> Self {
+        Budget {
+            nodes: NODE_BUDGET,
+            time: TIME_BUDGET,
+        }
+    }
+}
+
+/// Every recipe must need some ingredient, otherwise it could be baked forever.
+/// This is CPU-bound for up to `budget.time`, run it on a blocking thread.
+pub fn plan(recipes: &[Recipe], pantry: &[u64], budget: Budget) -> Mix {
+    let deadline = Instant::now() + budget.time;
+    let mut planner = Planner {
+        recipes,
+        pantry,
+        best: vec![0; recipes.len()],
+        best_value: 0,
+    };
+    let mut nodes = vec![Node {
+        lower: vec![0; recipes.len()],
+        upper: recipes
+            .iter()
+            .map(|recipe| max_batches(recipe, pantry))
+            .collect(),
+    }];
+
+    let mut solved = 0;
+    while let Some(node) = nodes.pop() {
+        if solved == budget.nodes || Instant::now() >= deadline {
+            return planner.into_mix(false);
+        }
+        solved += 1;
+        nodes.extend(planner.solve(node));
+    }
+
+    planner.into_mix(true)
+}
+
+/// Batches of `recipe` that fit in `pantry`.
+fn max_batches(recipe: &Recipe, pantry: &[u64]) -> u64 {
+    recipe
+        .needs
+        .iter()
+        .zip(pantry)
+        .filter(|(&need, _)| need > 0)
+        .map(|(&need, &have)| have / need)
+        .min()
+        .unwrap_or(0)
+}
+
+fn mix_value(recipes: &[Recipe], batches: &[u64]) -> u128 {
+    recipes
+        .iter()
+        .zip(batches)
+        .map(|(recipe, &count)| u128::from(recipe.value) * u128::from(count))
+        .sum()
+}
+
+/// What is left of `pantry` after baking `batches`, if they fit.
+fn leftover(recipes: &[Recipe], pantry: &[u64], batches: &[u64]) -> Option<Vec<u64>> {
+    let mut left = pantry.to_vec();
+    for (recipe, &count) in recipes.iter().zip(batches) {
+        for (have, &need) in left.iter_mut().zip(&recipe.needs) {
+            *have = have.checked_sub(need.checked_mul(count)?)?;
+        }
+    }
+
+    Some(left)
+}
+
+fn is_integral(x: f64) -> bool {
+    (x - x.round()).abs() <= 1e-9 * x.abs().max(1.0)
+}
+
+/// Mixes whose batches of each recipe are within `lower..=upper`.
+#[derive(Debug, Clone)]
+struct Node {
+    lower: Vec<u64>,
+    upper: Vec<u64>,
+}
+
+struct Planner<'a> {
+    recipes: &'a [Recipe],
+    pantry: &'a [u64],
+    best: Vec<u64>,
+    best_value: u128,
+}
+
+impl Planner<'_> {
+    fn into_mix(self, optimal: bool) -> Mix {
+        Mix {
+            batches: self.best,
+            value: self.best_value,
+            optimal,
+        }
+    }
+
+    /// Whether a node bounded by `bound` may hold a better mix than the best one.
+    /// Bounds are floats, so they are given some slack.
+    fn may_improve(&self, bound: Option<f64>) -> bool {
+        bound.is_none_or(|bound| bound * (1.0 + TOLERANCE) >= self.best_value as f64 + 1.0)
+    }
+
+    fn offer(&mut self, batches: Vec<u64>) {
+        let value = mix_value(self.recipes, &batches);
+        if value > self.best_value {
+            self.best_value = value;
+            self.best = batches;
+        }
+    }
+
+    /// Bounds `node`, keeps the mix found by rounding its relaxation and returns
+    /// the nodes it splits into, if it may hold a better mix.
+    fn solve(&mut self, mut node: Node) -> Vec<Node> {
+        let Some(left) = leftover(self.recipes, self.pantry, &node.lower) else {
+            return vec![];
+        };
+        let extra = self.recipes.iter().map(|recipe| max_batches(recipe, &left));
+        for ((upper, &lower), extra) in node.upper.iter_mut().zip(&node.lower).zip(extra) {
+            *upper = (*upper).min(lower.saturating_add(extra));
+        }
+        let base = mix_value(self.recipes, &node.lower);
+
+        // Batches on top of the lower bounds, for the recipes that may have some.
+        let free = (0..self.recipes.len())
+            .filter(|&r| node.upper[r] > node.lower[r])
+            .collect::<Vec<_>>();
+        let relaxed = relax(self.recipes, &left, &node, &free);
+        let bound = relaxed.as_ref().map(|(_, bound)| base as f64 + bound);
+        if !self.may_improve(bound) {
+            return vec![];
+        }
+
+        // Round the relaxation down, then fill what is left greedily.
+        let mut batches = node.lower.clone();
+        let mut left = left;
+        let relaxed_batches = relaxed.as_ref().map(|(batches, _)| batches.as_slice());
+        for pass in [relaxed_batches, None] {
+            for (k, &r) in free.iter().enumerate() {
+                let recipe = &self.recipes[r];
+                let wanted = match pass {
+                    Some(relaxed) if is_integral(relaxed[k]) => relaxed[k].round() as u64,
+                    Some(relaxed) => relaxed[k].floor() as u64,
+                    None => u64::MAX,
+                };
+                let count = wanted
+                    .min(node.upper[r] - batches[r])
+                    .min(max_batches(recipe, &left));
+                for (have, need) in left.iter_mut().zip(&recipe.needs) {
+                    *have -= need * count;
+                }
+                batches[r] += count;
+            }
+        }
+        self.offer(batches);
+        if !self.may_improve(bound) {
+            return vec![];
+        }
+
+        // Split on the most fractional recipe, or halve the widest range when the
+        // relaxation could not be solved.
+        let split = match &relaxed {
+            Some((relaxed, _)) => free
+                .iter()
+                .zip(relaxed)
+                .filter(|(_, &x)| !is_integral(x))
+                .min_by(|(_, a), (_, b)| {
+                    let distance = |x: f64| (x - x.floor() - 0.5).abs();
+                    distance(**a).total_cmp(&distance(**b))
+                })
+                .map(|(&r, &x)| (r, node.lower[r] + x.floor() as u64)),
+            None => free
+                .iter()
+                .max_by_key(|&&r| node.upper[r] - node.lower[r])
+                .map(|&r| (r, node.lower[r] + (node.upper[r] - node.lower[r]) / 2)),
+        };
+        let Some((r, at)) = split else {
+            return vec![];
+        };
+        let at = at.clamp(node.lower[r], node.upper[r].saturating_sub(1));
+
+        let mut below = node.clone();
+        below.upper[r] = at;
+        let mut above = node;
+        above.lower[r] = at + 1;
+        // The last node is solved first: dive into more batches of it.
+        vec![below, above]
+    }
+}
+
+/// Solves the linear relaxation of `node` over the `free` recipes, given the
+/// pantry `left` once its lower bounds are baked. Returns the extra batches of
+/// each free recipe and their value, or nothing if the simplex did not finish.
+fn relax(recipes: &[Recipe], left: &[u64], node: &Node, free: &[usize]) -> Option<(Vec<f64>, f64)> {
+    // Batches are counted in fractions of their range and ingredients in
+    // fractions of what is left, so every coefficient is at most 1.
+    let ranges = free
+        .iter()
+        .map(|&r| (node.upper[r] - node.lower[r]) as f64)
+        .collect::<Vec<_>>();
+    let values = free
+        .iter()
+        .zip(&ranges)
+        .map(|(&r, range)| recipes[r].value as f64 * range)
+        .collect::<Vec<_>>();
+    let top = values.iter().copied().fold(0.0, f64::max);
+    if top == 0.0 {
+        return Some((vec![0.0; free.len()], 0.0));
+    }
+
+    let mut rows = vec![];
+    for (i, &have) in left.iter().enumerate() {
+        if free.iter().any(|&r| recipes[r].needs[i] > 0) {
+            let row = free
+                .iter()
+                .zip(&ranges)
+                .map(|(&r, range)| recipes[r].needs[i] as f64 * range / have as f64)
+                .collect();
+            rows.push(row);
+        }
+    }
+    for k in 0..free.len() {
+        let mut row = vec![0.0; free.len()];
+        row[k] = 1.0;
+        rows.push(row);
+    }
+    let values = values.iter().map(|value| value / top).collect::<Vec<_>>();
+
+    let (fractions, objective) = simplex(&rows, &values)?;
+    let batches = fractions
+        .iter()
+        .zip(&ranges)
+        .map(|(fraction, range)| fraction * range)
+        .collect();
+    Some((batches, objective * top))
+}
+
+/// Maximizes `values · x` under `rows · x <= 1` and `x >= 0`, starting from the
+/// origin and using Bland's rule. Nothing if it takes too many pivots or the
+/// problem is unbounded.
+fn simplex(rows: &[Vec<f64>], values: &[f64]) -> Option<(Vec<f64>, f64)> {
+    let (m, n) = (rows.len(), values.len());
+    let rhs = n + m;
+    let mut tableau = vec![vec![0.0; rhs + 1]; m + 1];
+    for (i, row) in rows.iter().enumerate() {
+        tableau[i][..n].copy_from_slice(row);
+        tableau[i][n + i] = 1.0;
+        tableau[i][rhs] = 1.0;
+    }
+    for (j, value) in values.iter().enumerate() {
+        tableau[m][j] = -value;
+    }
+    let mut basis = (n..n + m).collect::<Vec<_>>();
+
+    for _ in 0..MAX_PIVOTS {
+        let Some(col) = (0..rhs).find(|&j| tableau[m][j] < -EPSILON) else {
+            let mut x = vec![0.0; n];
+            for (i, &var) in basis.iter().enumerate() {
+                if var < n {
+                    x[var] = tableau[i][rhs];
+                }
+            }
+            return Some((x, tableau[m][rhs]));
+        };
+        let row = (0..m)
+            .filter(|&i| tableau[i][col] > EPSILON)
+            .min_by(|&a, &b| {
+                let ratio = |i: usize| tableau[i][rhs] / tableau[i][col];
+                ratio(a).total_cmp(&ratio(b)).then(basis[a].cmp(&basis[b]))
+            })?;
+
+        let pivot = tableau[row][col];
+        for value in tableau[row].iter_mut() {
+            *value /= pivot;
+        }
+        let pivot_row = tableau[row].clone();
+        for (i, other) in tableau.iter_mut().enumerate() {
+            let factor = other[col];
+            if i != row && factor != 0.0 {
+                for (value, p) in other.iter_mut().zip(&pivot_row) {
+                    *value -= factor * p;
+                }
+            }
+        }
+        basis[row] = col;
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recipes(recipes: &[(&[u64], u64)]) -> Vec<Recipe> {
+        recipes
+            .iter()
+            .map(|&(needs, value)| Recipe {
+                needs: needs.to_vec(),
+                value,
+            })
+            .collect()
+    }
+
+    /// The best value by trying every mix, for small instances.
+    fn brute_force(recipes: &[Recipe], pantry: &[u64], batches: &mut Vec<u64>) -> u128 {
+        if batches.len() == recipes.len() {
+            return mix_value(recipes, batches);
+        }
+        let left = leftover(recipes, pantry, batches).unwrap();
+        let mut best = 0;
+        for count in 0..=max_batches(&recipes[batches.len()], &left) {
+            batches.push(count);
+            best = best.max(brute_force(recipes, pantry, batches));
+            batches.pop();
+        }
+
+        best
+    }
+
+    /// xorshift64, to draw instances without a dependency.
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 % n
+        }
+    }
+
+    fn assert_fits(recipes: &[Recipe], pantry: &[u64], mix: &Mix) {
+        assert!(leftover(recipes, pantry, &mix.batches).is_some());
+        assert_eq!(mix.value, mix_value(recipes, &mix.batches));
+    }
+
+    #[test]
+    fn nothing_fits_in_an_empty_pantry() {
+        let recipes = recipes(&[(&[1, 2], 3), (&[0, 1], 1)]);
+        let mix = plan(&recipes, &[0, 0], Budget::default());
+
+        assert_eq!(
+            mix,
+            Mix {
+                batches: vec![0, 0],
+                value: 0,
+                optimal: true,
+            }
+        );
+    }
+
+    #[test]
+    fn nothing_fits_when_an_ingredient_is_short() {
+        let recipes = recipes(&[(&[5, 1], 10)]);
+        let mix = plan(&recipes, &[4, 100], Budget::default());
+
+        assert_eq!(mix.batches, vec![0]);
+        assert!(mix.optimal);
+    }
+
+    #[test]
+    fn recipes_bounded_by_a_single_ingredient_stay_bounded() {
+        // Neither recipe needs the second ingredient, which is not limiting.
+        let recipes = recipes(&[(&[2, 0], 3), (&[3, 0], 5)]);
+        let mix = plan(&recipes, &[12, 0], Budget::default());
+
+        assert_eq!(mix.value, 20);
+        assert!(mix.optimal);
+        assert_fits(&recipes, &[12, 0], &mix);
+    }
+
+    #[test]
+    fn simplex_reports_unbounded_problems() {
+        assert_eq!(simplex(&[vec![1.0, 0.0]], &[1.0, 1.0]), None);
+        assert_eq!(
+            simplex(&[vec![1.0, 0.0]], &[1.0, 0.0]),
+            Some((vec![1.0, 0.0], 1.0))
+        );
+    }
+
+    #[test]
+    fn ties_are_still_optimal() {
+        let recipes = recipes(&[(&[1], 1), (&[1], 1), (&[2], 2)]);
+        let mix = plan(&recipes, &[7], Budget::default());
+
+        assert_eq!(mix.value, 7);
+        assert!(mix.optimal);
+        assert_fits(&recipes, &[7], &mix);
+        // The same input gives the same mix.
+        assert_eq!(mix, plan(&recipes, &[7], Budget::default()));
+    }
+
+    #[test]
+    fn ingredients_nobody_needs_are_ignored() {
+        let recipes = recipes(&[(&[0, 4, 0], 7), (&[0, 3, 0], 5)]);
+        let mix = plan(&recipes, &[0, 10, 1_000], Budget::default());
+
+        // 1 * 4 + 2 * 3 = 10.
+        assert_eq!(mix.value, 17);
+        assert!(mix.optimal);
+    }
+
+    #[test]
+    fn matches_brute_force_on_small_instances() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..300 {
+            let (count, ingredients) = (1 + rng.below(4), 1 + rng.below(3));
+            let recipes = (0..count)
+                .map(|_| {
+                    let mut needs = (0..ingredients)
+                        .map(|_| rng.below(6) * rng.below(2))
+                        .collect::<Vec<_>>();
+                    if needs.iter().all(|&need| need == 0) {
+                        needs[0] = 1 + rng.below(5);
+                    }
+                    Recipe {
+                        needs,
+                        value: rng.below(10),
+                    }
+                })
+                .collect::<Vec<_>>();
+            let pantry = (0..ingredients).map(|_| rng.below(30)).collect::<Vec<_>>();
+
+            let mix = plan(&recipes, &pantry, Budget::default());
+            assert!(mix.optimal);
+            assert_fits(&recipes, &pantry, &mix);
+            assert_eq!(
+                mix.value,
+                brute_force(&recipes, &pantry, &mut vec![]),
+                "{recipes:?} {pantry:?}"
+            );
+        }
+    }
+
+    /// As large an instance as day 7 accepts.
+    fn large_instance() -> (Vec<Recipe>, Vec<u64>) {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        let recipes = (0..16)
+            .map(|_| Recipe {
+                needs: (0..64).map(|_| 1 + rng.below(1_000)).collect(),
+                value: 1 + rng.below(1_000),
+            })
+            .collect();
+        let pantry = (0..64).map(|_| 10_000 + rng.below(100_000)).collect();
+
+        (recipes, pantry)
+    }
+
+    #[test]
+    fn gives_up_after_the_node_budget() {
+        let (recipes, pantry) = large_instance();
+        let budget = Budget {
+            nodes: 1,
+            time: Duration::from_secs(60),
+        };
+        let mix = plan(&recipes, &pantry, budget);
+
+        assert!(!mix.optimal);
+        assert!(mix.value > 0);
+        assert_fits(&recipes, &pantry, &mix);
+    }
+
+    #[test]
+    fn gives_up_after_the_time_budget() {
+        let (recipes, pantry) = large_instance();
+        let budget = Budget {
+            nodes: usize::MAX,
+            time: Duration::ZERO,
+        };
+        let mix = plan(&recipes, &pantry, budget);
+
+        assert!(!mix.optimal);
+        assert_eq!(mix.batches, vec![0; recipes.len()]);
+    }
+}
@@ -0,0 +1,400 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use sqlx::PgPool;
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use crate::clock::Clock;
+use crate::error::{AppError, ConfigError};
+
+/// When a key was saved and, if it has a TTL, when it stops being visible.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Saved {
+    pub saved_at: SystemTime,
+    pub expires_at: Option<SystemTime>,
+}
+
+impl Saved {
+    pub fn new(now: SystemTime, ttl: Option<Duration>) -> Result<Self, AppError> {
+        let expires_at = match ttl {
+            Some(ttl) => Some(
+                now.checked_add(ttl)
+                    .ok_or_else(|| AppError::BadRequest(format!("ttl {ttl:?} is too large")))?,
+            ),
+            None => None,
+        };
+
+        Ok(Saved {
+            saved_at: now,
+            expires_at,
+        })
+    }
+
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+/// Storage behind day 12's save/load. Every call takes the current time so the
+/// stores never read a clock themselves.
+#[async_trait]
+pub trait TimeStore: Send + Sync {
+    /// Saves `key`, replacing it if it already exists.
+    async fn save(&self, key: &str, saved: Saved) -> Result<(), AppError>;
+    /// When `key` was saved, `None` if it never was or has expired at `now`.
+    async fn load(&self, key: &str, now: SystemTime) -> Result<Option<SystemTime>, AppError>;
+    /// Keys not expired at `now`, sorted by key.
+    async fn keys(&self, now: SystemTime) -> Result<Vec<(String, Saved)>, AppError>;
+    /// Removes `key`, `false` if it never was saved or has expired at `now`.
+    async fn delete(&self, key: &str, now: SystemTime) -> Result<bool, AppError>;
+    /// Removes every key expired at `now`, returning how many there were.
+    async fn sweep(&self, now: SystemTime) -> Result<usize, AppError>;
+}
+
+/// Picks the store from `TIME_STORE` (`postgres`, `file` or `memory`). Defaults to
+/// Postgres when a pool is available, to memory otherwise. The file store writes
+/// to `TIME_STORE_PATH`, `day_12.json` by default.
+pub fn from_env(pool: Option<PgPool>) -> Result<Arc<dyn TimeStore>, ConfigError> {
+    let kind = std::env::var("TIME_STORE").ok();
+    match (kind.as_deref(), pool) {
+        (None | Some("postgres"), Some(pool)) => Ok(Arc::new(PgTimeStore { pool })),
+        (Some("postgres"), None) => Err(ConfigError(
+            "TIME_STORE=postgres needs a database".to_string(),
+        )),
+        (Some("file"), _) => {
+            let path = std::env::var("TIME_STORE_PATH").unwrap_or_else(|_| "day_12.json".into());
+            Ok(Arc::new(FileTimeStore::open(path.into())?))
+        }
+        (None | Some("memory"), _) => Ok(Arc::new(MemoryTimeStore::default())),
+        (Some(kind), _) => Err(ConfigError(format!("unknown time store {kind}"))),
+    }
+}
+
+/// Sweeps keys expired according to `clock` out of `store` every `interval`.
+pub fn spawn_sweeper(store: Arc<dyn TimeStore>, clock: Arc<dyn Clock>, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(interval);
+        loop {
+            ticks.tick().await;
+            match store.sweep(clock.now()).await {
+                Ok(0) => {}
+                Ok(swept) => info!("swept {swept} expired keys"),
+                Err(err) => warn!("failed to sweep expired keys: {err}"),
+            }
+        }
+    });
+}
+
+/// Saved keys of the memory and file stores.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Default)]
+#[serde(transparent)]
+struct Entries(BTreeMap<String, Saved>);
+
+impl Entries {
+    fn load(&self, key: &str, now: SystemTime) -> Option<SystemTime> {
+        self.0
+            .get(key)
+            .filter(|saved| !saved.is_expired(now))
+            .map(|saved| saved.saved_at)
+    }
+
+    fn keys(&self, now: SystemTime) -> Vec<(String, Saved)> {
+        self.0
+            .iter()
+            .filter(|(_, saved)| !saved.is_expired(now))
+            .map(|(key, saved)| (key.clone(), *saved))
+            .collect()
+    }
+
+    fn delete(&mut self, key: &str, now: SystemTime) -> bool {
+        self.0
+            .remove(key)
+            .is_some_and(|saved| !saved.is_expired(now))
+    }
+
+    fn sweep(&mut self, now: SystemTime) -> usize {
+        let before = self.0.len();
+        self.0.retain(|_, saved| !saved.is_expired(now));
+
+        before - self.0.len()
+    }
+}
+
+/// Keeps everything in process memory, lost on restart.
+#[derive(Default)]
+pub struct MemoryTimeStore {
+    entries: Mutex<Entries>,
+}
+
+impl MemoryTimeStore {
+    fn entries(&self) -> std::sync::MutexGuard<'_, Entries> {
+        self.entries.lock().expect("mutex was poisoned")
+    }
+}
+
+#[async_trait]
+impl TimeStore for MemoryTimeStore {
+    async fn save(&self, key: &str, saved: Saved) -> Result<(), AppError> {
+        self.entries().0.insert(key.to_string(), saved);
+
+        Ok(())
+    }
+
+    async fn load(&self, key: &str, now: SystemTime) -> Result<Option<SystemTime>, AppError> {
+        Ok(self.entries().load(key, now))
+    }
+
+    async fn keys(&self, now: SystemTime) -> Result<Vec<(String, Saved)>, AppError> {
+        Ok(self.entries().keys(now))
+    }
+
+    async fn delete(&self, key: &str, now: SystemTime) -> Result<bool, AppError> {
+        Ok(self.entries().delete(key, now))
+    }
+
+    async fn sweep(&self, now: SystemTime) -> Result<usize, AppError> {
+        Ok(self.entries().sweep(now))
+    }
+}
+
+/// Keeps everything in a JSON file, rewritten after every change.
+pub struct FileTimeStore {
+    path: PathBuf,
+    entries: tokio::sync::Mutex<Entries>,
+}
+
+impl FileTimeStore {
+    /// Reads the keys saved in `path`, starting empty if it does not exist yet.
+    pub fn open(path: PathBuf) -> Result<Self, ConfigError> {
+        let entries = match std::fs::read(&path) {
+            Ok(content) => serde_json::from_slice(&content).map_err(|err| {
+                ConfigError(format!("invalid time store {}: {err}", path.display()))
+            })?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Entries::default(),
+            Err(err) => {
+                return Err(ConfigError(format!(
+                    "failed to read time store {}: {err}",
+                    path.display()
+                )))
+            }
+        };
+
+        Ok(FileTimeStore {
+            path,
+            entries: tokio::sync::Mutex::new(entries),
+        })
+    }
+
+    /// Writes `entries` next to the store then renames it over, so a crash never
+    /// leaves a truncated file behind.
+    async fn persist(&self, entries: &Entries) -> Result<(), AppError> {
+        let write_failed = |err: std::io::Error| {
+            AppError::Internal(format!(
+                "failed to write time store {}: {err}",
+                self.path.display()
+            ))
+        };
+        let content =
+            serde_json::to_vec(entries).map_err(|err| AppError::Internal(err.to_string()))?;
+        let tmp = self.path.with_extension("tmp");
+        tokio::fs::write(&tmp, content)
+            .await
+            .map_err(write_failed)?;
+        tokio::fs::rename(&tmp, &self.path)
+            .await
+            .map_err(write_failed)?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TimeStore for FileTimeStore {
+    async fn save(&self, key: &str, saved: Saved) -> Result<(), AppError> {
+        let mut entries = self.entries.lock().await;
+        entries.0.insert(key.to_string(), saved);
+
+        self.persist(&entries).await
+    }
+
+    async fn load(&self, key: &str, now: SystemTime) -> Result<Option<SystemTime>, AppError> {
+        Ok(self.entries.lock().await.load(key, now))
+    }
+
+    async fn keys(&self, now: SystemTime) -> Result<Vec<(String, Saved)>, AppError> {
+        Ok(self.entries.lock().await.keys(now))
+    }
+
+    async fn delete(&self, key: &str, now: SystemTime) -> Result<bool, AppError> {
+        let mut entries = self.entries.lock().await;
+        let deleted = entries.delete(key, now);
+        self.persist(&entries).await?;
+
+        Ok(deleted)
+    }
+
+    async fn sweep(&self, now: SystemTime) -> Result<usize, AppError> {
+        let mut entries = self.entries.lock().await;
+        let swept = entries.sweep(now);
+        if swept > 0 {
+            self.persist(&entries).await?;
+        }
+
+        Ok(swept)
+    }
+}
+
+pub struct PgTimeStore {
+    pub pool: PgPool,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct SavedRow {
+    key: String,
+    saved_at: DateTime<Utc>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+#[async_trait]
+impl TimeStore for PgTimeStore {
+    async fn save(&self, key: &str, saved: Saved) -> Result<(), AppError> {
+        let sql = "INSERT INTO saved_strings (key, saved_at, expires_at) VALUES ($1, $2, $3)
+            ON CONFLICT (key) DO UPDATE SET
+                saved_at = EXCLUDED.saved_at,
+                expires_at = EXCLUDED.expires_at";
+        sqlx::query(sql)
+            .bind(key)
+            .bind(DateTime::<Utc>::from(saved.saved_at))
+            .bind(saved.expires_at.map(DateTime::<Utc>::from))
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn load(&self, key: &str, now: SystemTime) -> Result<Option<SystemTime>, AppError> {
+        let sql = "SELECT saved_at FROM saved_strings
+            WHERE key = $1 AND (expires_at IS NULL OR expires_at > $2)";
+        let saved_at = sqlx::query_scalar::<_, DateTime<Utc>>(sql)
+            .bind(key)
+            .bind(DateTime::<Utc>::from(now))
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(saved_at.map(SystemTime::from))
+    }
+
+    async fn keys(&self, now: SystemTime) -> Result<Vec<(String, Saved)>, AppError> {
+        let sql = "SELECT key, saved_at, expires_at FROM saved_strings
+            WHERE expires_at IS NULL OR expires_at > $1
+            ORDER BY key";
+        let rows = sqlx::query_as::<_, SavedRow>(sql)
+            .bind(DateTime::<Utc>::from(now))
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let saved = Saved {
+                    saved_at: row.saved_at.into(),
+                    expires_at: row.expires_at.map(SystemTime::from),
+                };
+                (row.key, saved)
+            })
+            .collect())
+    }
+
+    async fn delete(&self, key: &str, now: SystemTime) -> Result<bool, AppError> {
+        let sql = "DELETE FROM saved_strings
+            WHERE key = $1 AND (expires_at IS NULL OR expires_at > $2)";
+        let result = sqlx::query(sql)
+            .bind(key)
+            .bind(DateTime::<Utc>::from(now))
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn sweep(&self, now: SystemTime) -> Result<usize, AppError> {
+        let result = sqlx::query("DELETE FROM saved_strings WHERE expires_at <= $1")
+            .bind(DateTime::<Utc>::from(now))
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(seconds: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    fn saved(at_seconds: u64, ttl: Option<u64>) -> Saved {
+        Saved::new(at(at_seconds), ttl.map(Duration::from_secs)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn file_store_keeps_keys_across_reopening() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("day_12.json");
+
+        let store = FileTimeStore::open(path.clone()).unwrap();
+        store.save("forever", saved(100, None)).await.unwrap();
+        store.save("brief", saved(100, Some(10))).await.unwrap();
+        store.save("gone", saved(100, None)).await.unwrap();
+        assert!(store.delete("gone", at(105)).await.unwrap());
+        drop(store);
+
+        let store = FileTimeStore::open(path.clone()).unwrap();
+        assert_eq!(store.load("forever", at(105)).await.unwrap(), Some(at(100)));
+        assert_eq!(store.load("brief", at(105)).await.unwrap(), Some(at(100)));
+        assert_eq!(store.load("gone", at(105)).await.unwrap(), None);
+        assert_eq!(
+            store.keys(at(105)).await.unwrap(),
+            vec![
+                ("brief".to_string(), saved(100, Some(10))),
+                ("forever".to_string(), saved(100, None)),
+            ]
+        );
+        assert!(!path.with_extension("tmp").exists());
+    }
+
+    #[tokio::test]
+    async fn file_store_persists_sweeps() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("day_12.json");
+
+        let store = FileTimeStore::open(path.clone()).unwrap();
+        store.save("brief", saved(100, Some(10))).await.unwrap();
+        store.save("forever", saved(100, None)).await.unwrap();
+        assert_eq!(store.load("brief", at(110)).await.unwrap(), None);
+        assert_eq!(store.sweep(at(110)).await.unwrap(), 1);
+        assert_eq!(store.sweep(at(110)).await.unwrap(), 0);
+        drop(store);
+
+        let reopened = FileTimeStore::open(path).unwrap();
+        assert_eq!(reopened.entries.lock().await.0.len(), 1);
+        assert!(!reopened.delete("brief", at(0)).await.unwrap());
+    }
+
+    #[test]
+    fn file_store_starts_empty_but_rejects_invalid_files() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(FileTimeStore::open(dir.path().join("missing.json")).is_ok());
+
+        let path = dir.path().join("invalid.json");
+        std::fs::write(&path, "not json").unwrap();
+        assert!(FileTimeStore::open(path).is_err());
+    }
+}
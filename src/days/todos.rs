@@ -0,0 +1,167 @@
+use axum::{extract::State, http::StatusCode, routing::get, Router};
+use log::info;
+use sqlx::PgPool;
+
+use super::{Day, Resource};
+use crate::error::AppError;
+use crate::extract::{Json, Path, Query};
+
+pub const DAY: Day = Day {
+    number: 0,
+    title: "Todos",
+    requires: &[Resource::Database],
+    routes: &[
+        "POST /todos",
+        "GET /todos",
+        "GET /todos/:id",
+        "PUT /todos/:id",
+        "DELETE /todos/:id",
+    ],
+    router: |resources| get_routes(resources.pool.clone().expect("checked by the registry")),
+};
+
+pub fn get_routes(pool: PgPool) -> Router {
+    Router::new()
+        .route("/todos", get(list_todos).post(create_todo))
+        .route(
+            "/todos/:id",
+            get(get_todo).put(update_todo).delete(delete_todo),
+        )
+        .with_state(TodoState { pool })
+}
+
+#[derive(Clone)]
+pub struct TodoState {
+    pub pool: PgPool,
+}
+
+#[derive(Debug, sqlx::FromRow, serde::Serialize)]
+struct Todo {
+    id: i32,
+    note: String,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct TodoInput {
+    note: String,
+}
+
+impl TodoInput {
+    fn validate(self) -> Result<String, AppError> {
+        if self.note.trim().is_empty() {
+            return Err(AppError::Unprocessable(
+                "note must not be empty".to_string(),
+            ));
+        }
+
+        Ok(self.note)
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct TodoFilter {
+    #[serde(default)]
+    offset: i64,
+    #[serde(default = "default_limit")]
+    limit: i64,
+    /// Case-insensitive substring the note must contain.
+    q: Option<String>,
+}
+
+fn default_limit() -> i64 {
+    50
+}
+
+const MAX_LIMIT: i64 = 1000;
+
+fn not_found(id: i32) -> AppError {
+    AppError::NotFound(format!("no todo with id {id}"))
+}
+
+async fn create_todo(
+    State(state): State<TodoState>,
+    Json(input): Json<TodoInput>,
+) -> Result<(StatusCode, Json<Todo>), AppError> {
+    info!("todos create started");
+    let note = input.validate()?;
+    let todo = sqlx::query_as::<_, Todo>("INSERT INTO todos (note) VALUES ($1) RETURNING id, note")
+        .bind(note)
+        .fetch_one(&state.pool)
+        .await?;
+
+    Ok((StatusCode::CREATED, todo.into()))
+}
+
+async fn list_todos(
+    State(state): State<TodoState>,
+    Query(filter): Query<TodoFilter>,
+) -> Result<Json<Vec<Todo>>, AppError> {
+    info!("todos list started");
+    if filter.offset < 0 || !(1..=MAX_LIMIT).contains(&filter.limit) {
+        return Err(AppError::BadRequest(format!(
+            "offset must not be negative and limit must be between 1 and {MAX_LIMIT}"
+        )));
+    }
+
+    let sql = "SELECT id, note FROM todos
+        WHERE $1::TEXT IS NULL OR strpos(lower(note), lower($1)) > 0
+        ORDER BY id
+        OFFSET $2 LIMIT $3";
+    let todos = sqlx::query_as::<_, Todo>(sql)
+        .bind(filter.q)
+        .bind(filter.offset)
+        .bind(filter.limit)
+        .fetch_all(&state.pool)
+        .await?;
+
+    Ok(todos.into())
+}
+
+async fn get_todo(
+    Path(id): Path<i32>,
+    State(state): State<TodoState>,
+) -> Result<Json<Todo>, AppError> {
+    info!("todos get started");
+    let todo = sqlx::query_as::<_, Todo>("SELECT id, note FROM todos WHERE id = $1")
+        .bind(id)
+        .fetch_optional(&state.pool)
+        .await?
+        .ok_or_else(|| not_found(id))?;
+
+    Ok(todo.into())
+}
+
+async fn update_todo(
+    Path(id): Path<i32>,
+    State(state): State<TodoState>,
+    Json(input): Json<TodoInput>,
+) -> Result<Json<Todo>, AppError> {
+    info!("todos update started");
+    let note = input.validate()?;
+    let todo =
+        sqlx::query_as::<_, Todo>("UPDATE todos SET note = $2 WHERE id = $1 RETURNING id, note")
+            .bind(id)
+            .bind(note)
+            .fetch_optional(&state.pool)
+            .await?
+            .ok_or_else(|| not_found(id))?;
+
+    Ok(todo.into())
+}
+
+async fn delete_todo(
+    Path(id): Path<i32>,
+    State(state): State<TodoState>,
+) -> Result<StatusCode, AppError> {
+    info!("todos delete started");
+    let result = sqlx::query("DELETE FROM todos WHERE id = $1")
+        .bind(id)
+        .execute(&state.pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(not_found(id));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,232 @@
+use axum::{
+    extract::rejection::{JsonRejection, PathRejection, QueryRejection},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use log::error;
+use serde_json::json;
+use sqlx::error::ErrorKind;
+
+/// Error returned by every handler. Rendered as `{"error": code, "detail": ...}`
+/// with a status code depending on the variant.
+#[derive(Debug)]
+pub enum AppError {
+    /// The request could not be parsed (bad path segment, header, body...).
+    BadRequest(String),
+    /// The requested resource does not exist.
+    NotFound(String),
+    /// The request was well-formed but its content makes no sense.
+    Unprocessable(String),
+    /// Some items of a submitted batch are invalid, nothing was applied.
+    InvalidItems(Vec<ItemError>),
+    /// The body is in a format the endpoint does not accept.
+    UnsupportedMediaType(String),
+    /// The body is larger than the endpoint accepts.
+    PayloadTooLarge(String),
+    /// A third-party service answered with something unusable.
+    Upstream(String),
+    Database(sqlx::Error),
+    Internal(String),
+}
+
+/// Why one item of a batch was rejected. `index` is its position in the batch.
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct ItemError {
+    pub index: usize,
+    pub detail: String,
+}
+
+impl AppError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Unprocessable(_) | AppError::InvalidItems(_) => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            AppError::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            AppError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::Upstream(_) => StatusCode::BAD_GATEWAY,
+            AppError::Database(_) | AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::BadRequest(_) => "bad_request",
+            AppError::NotFound(_) => "not_found",
+            AppError::Unprocessable(_) => "unprocessable_entity",
+            AppError::InvalidItems(_) => "invalid_items",
+            AppError::UnsupportedMediaType(_) => "unsupported_media_type",
+            AppError::PayloadTooLarge(_) => "payload_too_large",
+            AppError::Upstream(_) => "upstream",
+            AppError::Database(_) => "database",
+            AppError::Internal(_) => "internal",
+        }
+    }
+
+    /// What the client is told. Database and internal errors are only described
+    /// in the logs, as they can hold queries or server paths.
+    pub fn detail(&self) -> String {
+        match self {
+            AppError::Database(_) => "database error".to_string(),
+            AppError::Internal(_) => "internal server error".to_string(),
+            _ => self.message(),
+        }
+    }
+
+    /// The whole description of the error, as logged.
+    fn message(&self) -> String {
+        match self {
+            AppError::BadRequest(detail)
+            | AppError::NotFound(detail)
+            | AppError::Unprocessable(detail)
+            | AppError::UnsupportedMediaType(detail)
+            | AppError::PayloadTooLarge(detail)
+            | AppError::Upstream(detail)
+            | AppError::Internal(detail) => detail.clone(),
+            AppError::InvalidItems(errors) => format!("{} invalid items", errors.len()),
+            AppError::Database(err) => err.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.code(), self.message())
+    }
+}
+
+impl std::error::Error for AppError {}
+
+/// A setting read at startup, usually from the environment, that cannot be used.
+/// Unlike [`AppError`] it never reaches a client.
+#[derive(Debug)]
+pub struct ConfigError(pub String);
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid configuration: {}", self.0)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl AppError {
+    /// Keeps the status an axum extractor rejected a request with.
+    fn from_rejection(status: StatusCode, detail: String) -> Self {
+        match status {
+            StatusCode::NOT_FOUND => AppError::NotFound(detail),
+            StatusCode::UNPROCESSABLE_ENTITY => AppError::Unprocessable(detail),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE => AppError::UnsupportedMediaType(detail),
+            StatusCode::PAYLOAD_TOO_LARGE => AppError::PayloadTooLarge(detail),
+            status if status.is_server_error() => AppError::Internal(detail),
+            _ => AppError::BadRequest(detail),
+        }
+    }
+}
+
+impl From<JsonRejection> for AppError {
+    fn from(rejection: JsonRejection) -> Self {
+        AppError::from_rejection(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<PathRejection> for AppError {
+    fn from(rejection: PathRejection) -> Self {
+        AppError::from_rejection(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for AppError {
+    fn from(rejection: QueryRejection) -> Self {
+        AppError::from_rejection(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(err: sqlx::Error) -> Self {
+        // Constraint violations come from the submitted data, not from the database.
+        if let Some(db_err) = err.as_database_error() {
+            if matches!(
+                db_err.kind(),
+                ErrorKind::UniqueViolation | ErrorKind::ForeignKeyViolation
+            ) {
+                return AppError::Unprocessable(db_err.message().to_string());
+            }
+        }
+
+        AppError::Database(err)
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        if status.is_server_error() {
+            error!("{self}");
+        }
+
+        let mut body = json!({
+            "error": self.code(),
+            "detail": self.detail(),
+        });
+        if let AppError::InvalidItems(errors) = &self {
+            body["errors"] = json!(errors);
+        }
+        let body = Json(body);
+
+        (status, body).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::HttpBody;
+
+    async fn rendered(err: AppError) -> (StatusCode, serde_json::Value) {
+        let response = err.into_response();
+        let status = response.status();
+        let mut body = response.into_body();
+        let mut bytes = vec![];
+        while let Some(chunk) = body.data().await {
+            bytes.extend_from_slice(&chunk.unwrap());
+        }
+
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_not_described_to_clients() {
+        let err = AppError::Internal("cannot read /srv/fixtures/25.json".to_string());
+        assert_eq!(
+            err.to_string(),
+            "internal: cannot read /srv/fixtures/25.json"
+        );
+        let (status, body) = rendered(err).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(
+            body,
+            json!({"error": "internal", "detail": "internal server error"})
+        );
+
+        let (status, body) = rendered(AppError::Database(sqlx::Error::PoolTimedOut)).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(
+            body,
+            json!({"error": "database", "detail": "database error"})
+        );
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_described() {
+        let (status, body) = rendered(AppError::BadRequest("invalid number 'x'".to_string())).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body,
+            json!({"error": "bad_request", "detail": "invalid number 'x'"})
+        );
+    }
+}
@@ -0,0 +1,419 @@
+use async_trait::async_trait;
+use axum::{
+    body::Body,
+    extract::{FromRequest, FromRequestParts},
+    http::{
+        header::{CONTENT_TYPE, COOKIE},
+        request::Parts,
+        HeaderMap, Request,
+    },
+    response::{IntoResponse, Response},
+};
+use base64::{
+    alphabet,
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+    Engine,
+};
+use cookie::Cookie;
+use futures::{future, TryStreamExt};
+use serde::{
+    de::{DeserializeOwned, Deserializer, SeqAccess, Visitor},
+    Serialize,
+};
+use std::{
+    io::{self, BufRead, BufReader, Read},
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+use tokio_util::io::{StreamReader, SyncIoBridge};
+
+use crate::error::{AppError, ItemError};
+
+/// Bytes of a body read as it arrives, the same as axum's default limit on
+/// buffered bodies.
+pub const BODY_LIMIT: usize = 2 * 1024 * 1024;
+
+/// [`axum::Json`], rejecting invalid bodies with an [`AppError`]. Also a response.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json<T>(pub T);
+
+#[async_trait]
+impl<S, T> FromRequest<S, Body> for Json<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request<Body>, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::Json(value) = axum::Json::from_request(req, state).await?;
+
+        Ok(Json(value))
+    }
+}
+
+impl<T: Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> Response {
+        axum::Json(self.0).into_response()
+    }
+}
+
+impl<T> From<T> for Json<T> {
+    fn from(value: T) -> Self {
+        Json(value)
+    }
+}
+
+/// [`axum::extract::Path`], rejecting invalid segments with an [`AppError`].
+#[derive(Debug)]
+pub struct Path<T>(pub T);
+
+#[async_trait]
+impl<S, T> FromRequestParts<S> for Path<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned + Send,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Path(value) =
+            axum::extract::Path::from_request_parts(parts, state).await?;
+
+        Ok(Path(value))
+    }
+}
+
+/// [`axum::extract::Query`], rejecting invalid query strings with an [`AppError`].
+#[derive(Debug)]
+pub struct Query<T>(pub T);
+
+#[async_trait]
+impl<S, T> FromRequestParts<S> for Query<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Query(value) =
+            axum::extract::Query::from_request_parts(parts, state).await?;
+
+        Ok(Query(value))
+    }
+}
+
+/// Media types a [`Batch`] can be uploaded as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchFormat {
+    /// A JSON array, `application/json`.
+    Json,
+    /// One item per row under a header row, `text/csv`.
+    Csv,
+    /// One JSON object per line, `application/x-ndjson`.
+    Ndjson,
+}
+
+impl BatchFormat {
+    fn from_headers(headers: &HeaderMap) -> Result<Self, AppError> {
+        let content_type = headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        let mime = content_type.split(';').next().unwrap_or_default().trim();
+
+        match mime.to_ascii_lowercase().as_str() {
+            "application/json" => Ok(BatchFormat::Json),
+            "text/csv" => Ok(BatchFormat::Csv),
+            "application/x-ndjson" => Ok(BatchFormat::Ndjson),
+            _ => Err(AppError::UnsupportedMediaType(format!(
+                "expected application/json, text/csv or application/x-ndjson, got '{content_type}'"
+            ))),
+        }
+    }
+}
+
+/// A batch of items negotiated on the request's `Content-Type`.
+///
+/// CSV and NDJSON bodies are parsed while they are received, every unparsable
+/// row is reported at once as [`AppError::InvalidItems`].
+pub struct Batch<T>(pub Vec<T>);
+
+#[async_trait]
+impl<S, T> FromRequest<S, Body> for Batch<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned + Send + 'static,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request<Body>, state: &S) -> Result<Self, Self::Rejection> {
+        let format = BatchFormat::from_headers(req.headers())?;
+        if format == BatchFormat::Json {
+            let Json(items) = Json::<Vec<T>>::from_request(req, state).await?;
+            return Ok(Batch(items));
+        }
+
+        let items = with_body_reader(
+            req.into_body(),
+            Some(BODY_LIMIT),
+            move |reader| match format {
+                BatchFormat::Csv => parse_csv(reader),
+                _ => parse_ndjson(reader),
+            },
+        )
+        .await?;
+
+        Ok(Batch(items))
+    }
+}
+
+/// Runs `f` on a blocking thread with a reader over `body`, fed as it arrives.
+/// Past `limit` bytes the reader fails and the body is rejected as too large,
+/// whatever `f` made of the error.
+async fn with_body_reader<R: Send + 'static>(
+    body: Body,
+    limit: Option<usize>,
+    f: impl FnOnce(Box<dyn Read + Send>) -> Result<R, AppError> + Send + 'static,
+) -> Result<R, AppError> {
+    let too_large = Arc::new(AtomicBool::new(false));
+    let mut received = 0usize;
+    let body = body.map_err(io::Error::other).and_then({
+        let too_large = too_large.clone();
+        move |chunk| {
+            received = received.saturating_add(chunk.len());
+            if limit.is_some_and(|limit| received > limit) {
+                too_large.store(true, Ordering::Relaxed);
+                return future::ready(Err(io::Error::other("body is too large")));
+            }
+            future::ready(Ok(chunk))
+        }
+    });
+    let reader = SyncIoBridge::new(StreamReader::new(body));
+
+    let result = tokio::task::spawn_blocking(move || f(Box::new(reader)))
+        .await
+        .map_err(|err| AppError::Internal(err.to_string()))?;
+    if too_large.load(Ordering::Relaxed) {
+        let limit = limit.unwrap_or_default();
+        return Err(AppError::PayloadTooLarge(format!(
+            "body must not be larger than {limit} bytes"
+        )));
+    }
+
+    result
+}
+
+/// Folds the items of a JSON array body into `init` while it is received, so
+/// the array never has to be held in memory. Bodies over `limit` bytes are
+/// rejected with [`AppError::PayloadTooLarge`].
+pub async fn fold_json_array<T, A>(
+    body: Body,
+    limit: Option<usize>,
+    init: A,
+    f: impl FnMut(&mut A, T) + Send + 'static,
+) -> Result<A, AppError>
+where
+    T: DeserializeOwned,
+    A: Send + 'static,
+{
+    with_body_reader(body, limit, move |reader| {
+        let mut de = serde_json::Deserializer::from_reader(BufReader::new(reader));
+        let mut acc = init;
+        de.deserialize_seq(FoldVisitor {
+            acc: &mut acc,
+            f,
+            item: PhantomData,
+        })
+        .and_then(|()| de.end())
+        .map_err(|err| AppError::BadRequest(format!("invalid JSON array: {err}")))?;
+
+        Ok(acc)
+    })
+    .await
+}
+
+struct FoldVisitor<'a, T, A, F> {
+    acc: &'a mut A,
+    f: F,
+    item: PhantomData<T>,
+}
+
+impl<'de, T, A, F> Visitor<'de> for FoldVisitor<'_, T, A, F>
+where
+    T: DeserializeOwned,
+    F: FnMut(&mut A, T),
+{
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("an array")
+    }
+
+    fn visit_seq<S: SeqAccess<'de>>(mut self, mut seq: S) -> Result<(), S::Error> {
+        while let Some(item) = seq.next_element::<T>()? {
+            (self.f)(self.acc, item);
+        }
+
+        Ok(())
+    }
+}
+
+fn read_failed(err: impl std::fmt::Display) -> AppError {
+    AppError::BadRequest(format!("failed to read body: {err}"))
+}
+
+fn collect<T>(items: Vec<T>, errors: Vec<ItemError>) -> Result<Vec<T>, AppError> {
+    if !errors.is_empty() {
+        return Err(AppError::InvalidItems(errors));
+    }
+
+    Ok(items)
+}
+
+fn parse_csv<T: DeserializeOwned>(reader: impl Read) -> Result<Vec<T>, AppError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+
+    let (mut items, mut errors) = (vec![], vec![]);
+    for (index, record) in reader.deserialize().enumerate() {
+        match record {
+            Ok(item) => items.push(item),
+            Err(err) if matches!(err.kind(), csv::ErrorKind::Io(_)) => {
+                return Err(read_failed(err))
+            }
+            Err(err) => errors.push(ItemError {
+                index,
+                detail: err.to_string(),
+            }),
+        }
+    }
+
+    collect(items, errors)
+}
+
+fn parse_ndjson<T: DeserializeOwned>(reader: impl Read) -> Result<Vec<T>, AppError> {
+    let (mut items, mut errors) = (vec![], vec![]);
+    for line in BufReader::new(reader).lines() {
+        let line = line.map_err(read_failed)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let index = items.len() + errors.len();
+        match serde_json::from_str(&line) {
+            Ok(item) => items.push(item),
+            Err(err) => errors.push(ItemError {
+                index,
+                detail: err.to_string(),
+            }),
+        }
+    }
+
+    collect(items, errors)
+}
+
+/// Every cookie sent in the request's `Cookie` headers. Cookies that cannot be
+/// parsed are only reported when the one looked for is missing.
+#[derive(Debug, Default)]
+pub struct Cookies {
+    cookies: Vec<Cookie<'static>>,
+    invalid: Vec<String>,
+}
+
+impl Cookies {
+    pub fn from_headers(headers: &HeaderMap) -> Result<Self, AppError> {
+        let mut cookies = Cookies::default();
+        for header in headers.get_all(COOKIE) {
+            let header = header
+                .to_str()
+                .map_err(|err| AppError::BadRequest(format!("invalid cookie header: {err}")))?;
+            for cookie in Cookie::split_parse(header.to_string()) {
+                match cookie {
+                    Ok(cookie) => cookies.cookies.push(cookie),
+                    Err(err) => cookies.invalid.push(err.to_string()),
+                }
+            }
+        }
+
+        Ok(cookies)
+    }
+
+    /// The first cookie called `name`.
+    pub fn get(&self, name: &str) -> Result<&Cookie<'static>, AppError> {
+        if let Some(cookie) = self.cookies.iter().find(|cookie| cookie.name() == name) {
+            return Ok(cookie);
+        }
+
+        let mut detail = format!("missing cookie '{name}'");
+        if !self.invalid.is_empty() {
+            detail = format!("{detail}, invalid cookies: {}", self.invalid.join(", "));
+        }
+        Err(AppError::BadRequest(detail))
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Cookies {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Cookies::from_headers(&parts.headers)
+    }
+}
+
+const ANY_PADDING: GeneralPurposeConfig =
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent);
+const STANDARD_ANY_PADDING: GeneralPurpose = GeneralPurpose::new(&alphabet::STANDARD, ANY_PADDING);
+const URL_SAFE_ANY_PADDING: GeneralPurpose = GeneralPurpose::new(&alphabet::URL_SAFE, ANY_PADDING);
+
+/// Decodes standard or URL-safe base64, padded or not, as found in cookies.
+pub fn decode_base64(value: &str) -> Result<Vec<u8>, AppError> {
+    let engine = if value.contains(['-', '_']) {
+        URL_SAFE_ANY_PADDING
+    } else {
+        STANDARD_ANY_PADDING
+    };
+
+    engine
+        .decode(value)
+        .map_err(|err| AppError::BadRequest(format!("invalid base64: {err}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(count: usize) -> Body {
+        let names = vec!["Elf"; count];
+        Body::from(serde_json::to_vec(&names).unwrap())
+    }
+
+    async fn count_names(body: Body, limit: Option<usize>) -> Result<usize, AppError> {
+        fold_json_array(body, limit, 0, |count, _: String| *count += 1).await
+    }
+
+    #[tokio::test]
+    async fn bodies_up_to_the_limit_are_read() {
+        // `["Elf",...]` takes 6 bytes a name and one for the closing bracket.
+        assert_eq!(count_names(names(100), Some(601)).await.unwrap(), 100);
+        assert_eq!(count_names(names(100_000), None).await.unwrap(), 100_000);
+    }
+
+    #[tokio::test]
+    async fn bodies_over_the_limit_are_too_large() {
+        let err = count_names(names(100), Some(600)).await.unwrap_err();
+        assert!(matches!(err, AppError::PayloadTooLarge(_)), "{err}");
+
+        let csv = Body::from(format!("id\n{}", "1\n".repeat(BODY_LIMIT)));
+        let err = with_body_reader(csv, Some(BODY_LIMIT), parse_csv::<(i32,)>)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AppError::PayloadTooLarge(_)), "{err}");
+    }
+}
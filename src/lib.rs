@@ -0,0 +1,32 @@
+pub mod clock;
+pub mod countries;
+pub mod days;
+pub mod error;
+pub mod extract;
+pub mod lru;
+pub mod stats;
+pub mod tz;
+
+use axum::{http::StatusCode, routing::get, Router};
+use sqlx::migrate::Migrator;
+
+use days::{DaysConfig, Resources};
+
+/// Schema migrations from `migrations/`, run at startup by both entrypoints.
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+async fn ok() -> Result<String, StatusCode> {
+    Ok(String::from("okay"))
+}
+
+/// The whole application, shared by the Shuttle and the standalone entrypoints.
+pub fn app(config: &DaysConfig, resources: &Resources) -> Router {
+    let router = Router::new()
+        .route("/", get(ok))
+        .merge(days::get_routes(config, resources));
+
+    #[cfg(feature = "clock-admin")]
+    let router = router.merge(clock::get_routes(resources.fake_clock.clone()));
+
+    router
+}
@@ -0,0 +1,129 @@
+//! A map holding a bounded number of entries, dropping the least recently used
+//! one to make room. Meant for small caches behind a `Mutex`: finding the entry
+//! to drop walks the whole map.
+
+use std::{borrow::Borrow, collections::HashMap, hash::Hash};
+
+struct Entry<V> {
+    value: V,
+    /// When it was last read or written, on a counter of accesses.
+    used_at: u64,
+}
+
+pub struct LruMap<K, V> {
+    entries: HashMap<K, Entry<V>>,
+    capacity: usize,
+    accesses: u64,
+}
+
+impl<K: Hash + Eq + Clone, V> LruMap<K, V> {
+    /// An empty map holding at most `capacity` entries, at least one.
+    pub fn new(capacity: usize) -> Self {
+        LruMap {
+            entries: HashMap::new(),
+            capacity: capacity.max(1),
+            accesses: 0,
+        }
+    }
+
+    fn access(&mut self) -> u64 {
+        self.accesses += 1;
+        self.accesses
+    }
+
+    /// The value of `key`, which becomes the most recently used entry.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let used_at = self.access();
+        let entry = self.entries.get_mut(key)?;
+        entry.used_at = used_at;
+
+        Some(&entry.value)
+    }
+
+    /// Sets the value of `key`. Returns the key dropped to make room, if any.
+    pub fn insert(&mut self, key: K, value: V) -> Option<K> {
+        let used_at = self.access();
+        let mut dropped = None;
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            dropped = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.used_at)
+                .map(|(key, _)| key.clone());
+            if let Some(dropped) = &dropped {
+                self.entries.remove(dropped);
+            }
+        }
+        self.entries.insert(key, Entry { value, used_at });
+
+        dropped
+    }
+
+    /// Keeps only the entries `f` returns `true` for.
+    pub fn retain(&mut self, mut f: impl FnMut(&K, &V) -> bool) {
+        self.entries.retain(|key, entry| f(key, &entry.value));
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_least_recently_used_entry_is_dropped() {
+        let mut map = LruMap::new(3);
+        for key in ["a", "b", "c"] {
+            assert_eq!(map.insert(key, key.len()), None);
+        }
+        // Reading "a" makes "b" the oldest.
+        assert_eq!(map.get("a"), Some(&1));
+        assert_eq!(map.insert("d", 1), Some("b"));
+        assert_eq!(map.get("b"), None);
+        // So does writing it.
+        map.insert("c", 2);
+        assert_eq!(map.insert("e", 1), Some("a"));
+        assert_eq!(map.get("c"), Some(&2));
+        assert_eq!(map.len(), 3);
+    }
+
+    #[test]
+    fn replacing_a_value_drops_nothing() {
+        let mut map = LruMap::new(2);
+        map.insert("a".to_string(), 1);
+        map.insert("b".to_string(), 2);
+        assert_eq!(map.insert("a".to_string(), 3), None);
+        assert_eq!(map.get("a"), Some(&3));
+        assert_eq!(map.get("b"), Some(&2));
+    }
+
+    #[test]
+    fn retained_entries_make_room() {
+        let mut map = LruMap::new(2);
+        map.insert(1, "odd");
+        map.insert(2, "even");
+        map.retain(|key, _| key % 2 == 0);
+        assert_eq!(map.insert(3, "odd"), None);
+        assert_eq!(map.len(), 2);
+        assert!(!map.is_empty());
+    }
+
+    #[test]
+    fn holds_at_least_one_entry() {
+        let mut map = LruMap::new(0);
+        assert_eq!(map.insert(1, ()), None);
+        assert_eq!(map.insert(2, ()), Some(1));
+        assert_eq!(map.get(&2), Some(&()));
+    }
+}
@@ -0,0 +1,73 @@
+//! Statistics shared by the days summarizing numbers.
+
+use crate::error::AppError;
+
+/// Parses comma separated percentiles between 0 and 1, quartiles by default.
+pub fn parse_percentiles(p: Option<&str>) -> Result<Vec<f64>, AppError> {
+    match p {
+        None => Ok(vec![0.25, 0.5, 0.75]),
+        Some(p) => p
+            .split(',')
+            .map(|p| match p.trim().parse::<f64>() {
+                Ok(p) if (0.0..=1.0).contains(&p) => Ok(p),
+                _ => Err(AppError::BadRequest(format!(
+                    "percentile '{p}' is not a number between 0 and 1"
+                ))),
+            })
+            .collect(),
+    }
+}
+
+/// The `percentile` (between 0 and 1) of non-empty `sorted` values, interpolated
+/// linearly between the closest two like Postgres' `percentile_cont`.
+pub fn percentile_cont(sorted: &[f64], percentile: f64) -> f64 {
+    let position = percentile * (sorted.len() - 1) as f64;
+    let (lower, upper) = (position.floor() as usize, position.ceil() as usize);
+
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (position - lower as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles_default_to_quartiles() {
+        assert_eq!(parse_percentiles(None).unwrap(), [0.25, 0.5, 0.75]);
+        assert_eq!(
+            parse_percentiles(Some("0, 0.9 ,1")).unwrap(),
+            [0.0, 0.9, 1.0]
+        );
+    }
+
+    #[test]
+    fn percentiles_outside_0_and_1_are_rejected() {
+        for p in ["1.5", "-0.1", "50", "NaN", "", "0.5,"] {
+            assert!(parse_percentiles(Some(p)).is_err(), "{p}");
+        }
+    }
+
+    #[test]
+    fn edges_are_the_smallest_and_largest_values() {
+        let sorted = [1.0, 2.0, 4.0, 8.0];
+        assert_eq!(percentile_cont(&sorted, 0.0), 1.0);
+        assert_eq!(percentile_cont(&sorted, 1.0), 8.0);
+    }
+
+    #[test]
+    fn a_single_value_is_every_percentile() {
+        for p in [0.0, 0.25, 0.5, 1.0] {
+            assert_eq!(percentile_cont(&[7.0], p), 7.0);
+        }
+    }
+
+    #[test]
+    fn values_are_interpolated_between_neighbours() {
+        let sorted = [1.0, 2.0, 4.0, 8.0];
+        // Positions 1.5, 0.75 and 2.7 of the 0 to 3 range.
+        assert_eq!(percentile_cont(&sorted, 0.5), 3.0);
+        assert_eq!(percentile_cont(&sorted, 0.25), 1.75);
+        assert!((percentile_cont(&sorted, 0.9) - 6.8).abs() < 1e-9);
+        assert_eq!(percentile_cont(&[10.0, 20.0], 0.5), 15.0);
+    }
+}
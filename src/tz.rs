@@ -0,0 +1,142 @@
+//! IANA time zones, from the tz database compiled in by `chrono-tz`.
+//!
+//! `chrono-tz` lists transitions until 2100. Later dates use the same rules as
+//! the year before 2100 with the same calendar, as the zone's last rules are
+//! expected to keep applying.
+
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, Offset, TimeZone as _, Utc};
+use chrono_tz::Tz;
+
+use crate::error::AppError;
+
+/// First year past the transitions `chrono-tz` lists.
+const LAST_LISTED_YEAR: i32 = 2100;
+
+#[derive(Debug, Clone, Copy)]
+pub struct TimeZone {
+    tz: Tz,
+}
+
+impl TimeZone {
+    pub fn utc() -> Self {
+        TimeZone { tz: Tz::UTC }
+    }
+
+    /// Looks `name` (e.g. `Europe/Paris`) up in the tz database.
+    pub fn named(name: &str) -> Result<Self, AppError> {
+        let tz = name
+            .parse()
+            .map_err(|_| AppError::BadRequest(format!("unknown time zone '{name}'")))?;
+
+        Ok(TimeZone { tz })
+    }
+
+    pub fn name(&self) -> &str {
+        self.tz.name()
+    }
+
+    /// UTC offset in effect at `datetime`.
+    pub fn offset_at(&self, datetime: DateTime<Utc>) -> FixedOffset {
+        let naive = datetime.naive_utc();
+        let naive = match same_calendar_year(naive.year()) {
+            Some(year) => naive.with_year(year).unwrap_or(naive),
+            None => naive,
+        };
+
+        self.tz.offset_from_utc_datetime(&naive).fix()
+    }
+
+    pub fn to_local(&self, datetime: DateTime<Utc>) -> DateTime<FixedOffset> {
+        datetime.with_timezone(&self.offset_at(datetime))
+    }
+}
+
+/// For a year past the listed transitions, the last listed year starting on the
+/// same weekday and as long, so that rules such as "last Sunday of March" fall
+/// on the same dates. The 28 years before 2100 hold every kind of year.
+fn same_calendar_year(year: i32) -> Option<i32> {
+    if year < LAST_LISTED_YEAR {
+        return None;
+    }
+    let kind = |year: i32| {
+        let first = NaiveDate::from_ymd_opt(year, 1, 1)?;
+        let days = NaiveDate::from_ymd_opt(year + 1, 1, 1)? - first;
+        Some((first.weekday(), days))
+    };
+    let wanted = kind(year)?;
+
+    (LAST_LISTED_YEAR - 28..LAST_LISTED_YEAR)
+        .rev()
+        .find(|&candidate| kind(candidate) == Some(wanted))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Offset of `zone` at `utc`, written `%Y-%m-%d %H:%M:%S`.
+    fn offset(zone: &str, utc: &str) -> i32 {
+        let datetime = chrono::NaiveDateTime::parse_from_str(utc, "%Y-%m-%d %H:%M:%S").unwrap();
+        TimeZone::named(zone)
+            .unwrap()
+            .offset_at(datetime.and_utc())
+            .local_minus_utc()
+    }
+
+    #[test]
+    fn unknown_zones_are_rejected() {
+        assert!(TimeZone::named("Mars/Olympus_Mons").is_err());
+        assert!(TimeZone::named("../../etc/passwd").is_err());
+        assert!(TimeZone::named("").is_err());
+    }
+
+    #[test]
+    fn follows_current_daylight_saving_rules() {
+        // The US rules changed in 2007, which slim tz files only encode in
+        // their footer.
+        assert_eq!(offset("America/New_York", "2024-03-09 12:00:00"), -5 * 3600);
+        assert_eq!(offset("America/New_York", "2024-03-11 12:00:00"), -4 * 3600);
+        assert_eq!(offset("America/New_York", "2024-11-04 12:00:00"), -5 * 3600);
+        assert_eq!(offset("Europe/Paris", "2023-07-01 00:00:00"), 2 * 3600);
+    }
+
+    #[test]
+    fn keeps_daylight_saving_after_2037() {
+        assert_eq!(offset("Europe/Paris", "2050-07-01 00:00:00"), 2 * 3600);
+        assert_eq!(offset("Europe/Paris", "2050-12-01 00:00:00"), 3600);
+        assert_eq!(offset("Australia/Sydney", "2099-01-15 00:00:00"), 11 * 3600);
+    }
+
+    #[test]
+    fn keeps_daylight_saving_after_2100() {
+        // Last Sunday of March 2150 is the 29th, of October the 25th.
+        assert_eq!(offset("Europe/Paris", "2150-03-29 00:59:59"), 3600);
+        assert_eq!(offset("Europe/Paris", "2150-03-29 01:00:00"), 2 * 3600);
+        assert_eq!(offset("Europe/Paris", "2150-10-25 00:59:59"), 2 * 3600);
+        assert_eq!(offset("Europe/Paris", "2150-10-25 01:00:00"), 3600);
+        // Up to the highest ULID timestamp, in August 10889.
+        assert_eq!(offset("Europe/Paris", "+10889-07-01 00:00:00"), 2 * 3600);
+        assert_eq!(
+            offset("America/New_York", "+10889-08-02 05:31:50"),
+            -4 * 3600
+        );
+        assert_eq!(
+            offset("America/New_York", "+10889-12-02 05:31:50"),
+            -5 * 3600
+        );
+    }
+
+    #[test]
+    fn same_calendar_year_matches_leap_years_and_weekdays() {
+        assert_eq!(same_calendar_year(2099), None);
+        // 2100 is not a leap year, unlike 2096 and 2072.
+        let year = same_calendar_year(2100).unwrap();
+        assert!(!NaiveDate::from_ymd_opt(year, 1, 1).unwrap().leap_year());
+        for year in [2100, 2104, 2150, 2400, 10889] {
+            let equivalent = same_calendar_year(year).unwrap();
+            let first = |year| NaiveDate::from_ymd_opt(year, 1, 1).unwrap();
+            assert_eq!(first(year).weekday(), first(equivalent).weekday());
+            assert_eq!(first(year).leap_year(), first(equivalent).leap_year());
+        }
+    }
+}